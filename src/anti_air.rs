@@ -0,0 +1,72 @@
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+// Queens and spores already scale with the air threat Surveillance reports; this handles the
+// army's answer to it.
+impl FlourishBot {
+	/// Supply of anti-air army we want for each supply of enemy air that can hit us.
+	const ANTI_AIR_SUPPLY_RATIO: f32 = 1.5;
+	/// How many corruptors we want to chase down flying buildings.
+	const HUNT_CORRUPTORS: usize = 4;
+	const ANTI_AIR_UNIT_TYPES: &'static [UnitTypeId] = &[UnitTypeId::Hydralisk, UnitTypeId::Corruptor, UnitTypeId::Mutalisk];
+
+	/// Whether we need army units that can shoot up, against air units or against buildings that
+	/// have lifted off while we're hunting for the last of them.
+	pub fn wants_anti_air(&self) -> bool {
+		self.surveillance.air_threat_supply() > 0.0 || self.base_hunt.hunting_flying_structures()
+	}
+
+	/// Whether the air we're up against calls for corruptors rather than hydralisks.
+	fn wants_corruptors(&self) -> bool {
+		self.surveillance.has_seen_capital_ships() || self.base_hunt.hunting_flying_structures()
+	}
+
+	/// The tech building our anti-air needs, if we want any.
+	pub fn anti_air_tech(&self) -> Option<UnitTypeId> {
+		if !self.wants_anti_air() {
+			None
+		} else if self.wants_corruptors() {
+			Some(UnitTypeId::Spire)
+		} else {
+			Some(UnitTypeId::HydraliskDen)
+		}
+	}
+
+	fn anti_air_supply(&self) -> f32 {
+		Self::ANTI_AIR_UNIT_TYPES
+			.iter()
+			.map(|t| self.counter().all().count(*t) as f32 * self.get_unit_cost(*t).supply)
+			.sum()
+	}
+
+	/// Trains hydralisks, or corruptors against capital ships and flying buildings, until our
+	/// anti-air matches the enemy's air.
+	pub fn train_anti_air(&mut self) {
+		let (tech, unit) = match self.anti_air_tech() {
+			Some(UnitTypeId::Spire) => (UnitTypeId::Spire, UnitTypeId::Corruptor),
+			Some(tech) => (tech, UnitTypeId::Hydralisk),
+			None => return,
+		};
+		if !self.units.my.structures.iter().of_type(tech).ready().any(|_| true) {
+			return;
+		}
+
+		let mut wanted = self.surveillance.air_threat_supply() * Self::ANTI_AIR_SUPPLY_RATIO;
+		if self.base_hunt.hunting_flying_structures() {
+			wanted = wanted.max(Self::HUNT_CORRUPTORS as f32 * self.get_unit_cost(UnitTypeId::Corruptor).supply);
+		}
+
+		let supply = self.get_unit_cost(unit).supply;
+		let mut anti_air = self.anti_air_supply();
+		while anti_air < wanted && self.can_afford(unit, true) {
+			if let Some(larva) = self.units.my.larvas.pop() {
+				larva.train(unit, false);
+				self.subtract_resources(unit, true);
+				anti_air += supply;
+			} else {
+				break;
+			}
+		}
+	}
+}
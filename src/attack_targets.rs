@@ -0,0 +1,104 @@
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+/// An enemy base, or group of structures, we know about.
+pub struct EnemyBase {
+	pub position: Point2,
+	pub has_townhall: bool,
+	/// Enemy army supply seen near it recently plus its static defense.
+	pub defense: f32,
+}
+
+// Doing this in FlourishBot so we can combine what Surveillance remembers with the map's
+// expansions.
+impl FlourishBot {
+	/// Structures within this distance of an expansion belong to that base.
+	const ENEMY_BASE_RADIUS: f32 = 15.0;
+	/// Enemy units seen near a base this recently count towards its defense.
+	const BASE_DEFENSE_SECONDS: f32 = 60.0;
+	/// How much army supply one piece of static defense counts as.
+	const STATIC_DEFENSE_SUPPLY: f32 = 3.0;
+	/// Every this much distance to a target counts as much as one more supply defending it.
+	const ATTACK_DISTANCE_PER_SUPPLY: f32 = 20.0;
+
+	/// The enemy bases we know about, built from the structures Surveillance remembers. Structures
+	/// away from any expansion are grouped on their own.
+	pub fn known_enemy_bases(&self) -> Vec<EnemyBase> {
+		let game_loop = self.state.observation.game_loop();
+		let mut bases: Vec<EnemyBase> = Vec::new();
+
+		for structure in self.surveillance.remembered_enemy_structures() {
+			let position = self
+				.expansions
+				.iter()
+				.map(|e| e.loc)
+				.filter(|loc| structure.is_closer(Self::ENEMY_BASE_RADIUS, *loc))
+				.min_by_key(|loc| structure.distance_squared(*loc) as u32)
+				.unwrap_or_else(|| structure.position());
+
+			let index = match bases.iter().position(|b| b.position.is_closer(Self::ENEMY_BASE_RADIUS, position)) {
+				Some(index) => index,
+				None => {
+					let defense = self.surveillance.enemy_supply_seen_near(
+						position,
+						Self::ENEMY_BASE_RADIUS,
+						Self::BASE_DEFENSE_SECONDS,
+						game_loop,
+					);
+					bases.push(EnemyBase {
+						position,
+						has_townhall: false,
+						defense,
+					});
+					bases.len() - 1
+				}
+			};
+
+			let base = &mut bases[index];
+			base.has_townhall |= structure.is_townhall();
+			if structure.can_attack() {
+				base.defense += Self::STATIC_DEFENSE_SUPPLY;
+			}
+		}
+
+		bases
+	}
+
+	/// The expansion the army should go and look at when it doesn't know of any enemy structures:
+	/// the one that's gone longest without being seen, never seen first, and closest to the army
+	/// among those.
+	fn unexplored_expansion(&self, from: Point2) -> Option<Point2> {
+		let own = self.units.my.townhalls.clone();
+		self.expansions
+			.iter()
+			.enumerate()
+			.filter(|(_, e)| !own.iter().any(|t| t.is_closer(Self::ENEMY_BASE_RADIUS, e.loc)))
+			.min_by_key(|(i, e)| {
+				(
+					self.surveillance.expansion_last_scouted(*i).map_or(0, |l| l + 1),
+					e.loc.distance_squared(from) as u32,
+				)
+			})
+			.map(|(_, e)| e.loc)
+	}
+
+	/// Where the army should attack from `from`. Known enemy bases come first, preferring ones
+	/// with a townhall and as little defense as possible while not being too far away. With no
+	/// known structures left, we search the expansions instead.
+	pub fn choose_attack_target(&self, from: Point2) -> Point2 {
+		let score = |base: &EnemyBase| {
+			let distance = base.position.distance(from) / Self::ATTACK_DISTANCE_PER_SUPPLY;
+			let townhall = if base.has_townhall { 0.0 } else { Self::STATIC_DEFENSE_SUPPLY };
+			base.defense + distance + townhall
+		};
+
+		self.known_enemy_bases()
+			.iter()
+			.min_by(|a, b| score(a).partial_cmp(&score(b)).unwrap_or(std::cmp::Ordering::Equal))
+			.map(|b| b.position)
+			.or_else(|| if self.base_hunt.active { self.hunt_targets(1, false).first().copied() } else { None })
+			.or_else(|| self.unexplored_expansion(from))
+			.unwrap_or(self.enemy_start)
+	}
+}
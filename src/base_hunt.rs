@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+#[derive(Default)]
+pub struct BaseHunt {
+	/// Whether we've lost track of every enemy structure and are searching the map for the rest.
+	pub active: bool,
+	/// Whether we've ever seen an enemy structure in the air.
+	seen_flying_structures: bool,
+	/// The game loop each cell of the search grid was last in vision, row by row.
+	cleared: Vec<Option<u32>>,
+	width: usize,
+	height: usize,
+}
+
+impl BaseHunt {
+	/// Side length of a search grid cell. About what a zergling can see across.
+	const CELL_SIZE: f32 = 10.0;
+
+	/// Whether we're searching for the last enemy buildings and some of them could have flown off
+	/// anywhere. Lifting early on is routine for Terran, so that alone doesn't count.
+	pub fn hunting_flying_structures(&self) -> bool {
+		self.active && self.seen_flying_structures
+	}
+
+	fn cell_center(&self, index: usize) -> Point2 {
+		let (x, y) = (index % self.width, index / self.width);
+		Point2::new((x as f32 + 0.5) * Self::CELL_SIZE, (y as f32 + 0.5) * Self::CELL_SIZE)
+	}
+
+	/// The game loop the search cell containing this point was last in vision, if it ever was.
+	pub fn last_cleared(&self, point: Point2) -> Option<u32> {
+		let (x, y) = ((point.x / Self::CELL_SIZE) as usize, (point.y / Self::CELL_SIZE) as usize);
+		if x >= self.width || y >= self.height {
+			return None;
+		}
+		self.cleared[y * self.width + x]
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to BaseHunt while viewing the map.
+impl FlourishBot {
+	/// We don't start hunting before this, in case we just haven't scouted yet.
+	const BASE_HUNT_MIN_TIME: f32 = 8.0 * 60.0;
+	/// How many places the main army splits up to search at once.
+	const HUNT_GROUPS: usize = 4;
+
+	/// Marks the search cells currently in vision as cleared, and switches base hunting on once
+	/// we know of no enemy structures at all, or off again when we find some.
+	pub fn update_base_hunt(&mut self) {
+		if self.base_hunt.cleared.is_empty() {
+			let size = self.game_info.map_size;
+			self.base_hunt.width = (size.x as f32 / BaseHunt::CELL_SIZE).ceil() as usize;
+			self.base_hunt.height = (size.y as f32 / BaseHunt::CELL_SIZE).ceil() as usize;
+			self.base_hunt.cleared = vec![None; self.base_hunt.width * self.base_hunt.height];
+		}
+
+		let game_loop = self.state.observation.game_loop();
+		let visible = (0..self.base_hunt.cleared.len())
+			.filter(|i| self.is_visible(self.base_hunt.cell_center(*i)))
+			.collect::<Vec<usize>>();
+		for i in visible {
+			self.base_hunt.cleared[i] = Some(game_loop);
+		}
+
+		if self.units.enemy.structures.iter().any(|s| s.is_flying()) {
+			self.base_hunt.seen_flying_structures = true;
+		}
+
+		self.base_hunt.active = self.time > Self::BASE_HUNT_MIN_TIME && self.surveillance.remembered_enemy_structures().next().is_none();
+	}
+
+	/// The `count` places most in need of a look: expansions first, then the rest of the map,
+	/// each going from the longest since it was last seen. Only air units can search cells that
+	/// can't be walked to, which is where lifted buildings usually hide.
+	pub fn hunt_targets(&self, count: usize, flying: bool) -> Vec<Point2> {
+		let mut candidates = self
+			.expansions
+			.iter()
+			.map(|e| (e.loc, self.base_hunt.last_cleared(e.loc), 0))
+			.collect::<Vec<(Point2, Option<u32>, u8)>>();
+		candidates.extend(
+			(0..self.base_hunt.cleared.len())
+				.map(|i| self.base_hunt.cell_center(i))
+				.filter(|p| flying || self.is_pathable(*p))
+				.map(|p| (p, self.base_hunt.last_cleared(p), 1)),
+		);
+
+		// Never seen sorts before seen, then oldest sighting first.
+		candidates.sort_by_key(|(p, cleared, is_cell)| {
+			(cleared.map_or(0, |l| l + 1), *is_cell, p.distance_squared(self.start_location) as u32)
+		});
+		candidates.into_iter().take(count).map(|(p, _, _)| p).collect()
+	}
+
+	/// Splits ground and air units into up to HUNT_GROUPS groups each and sends each group to the
+	/// closest hunt target no other group has taken. Returns where each unit should search, by tag.
+	pub fn assign_hunt_groups(&self, units: &Units) -> HashMap<u64, Point2> {
+		let air = units.filter(|u| u.is_flying());
+		let ground = units.filter(|u| !u.is_flying());
+
+		let mut assigned = Self::assign_groups(&ground, self.hunt_targets(Self::HUNT_GROUPS, false));
+		assigned.extend(Self::assign_groups(&air, self.hunt_targets(Self::HUNT_GROUPS, true)));
+		assigned
+	}
+
+	fn assign_groups(units: &Units, mut targets: Vec<Point2>) -> HashMap<u64, Point2> {
+		if targets.is_empty() || units.is_empty() {
+			return HashMap::new();
+		}
+
+		// Grouped by tag so units stay with the same group from one step to the next.
+		let mut tags = units.iter().map(|u| u.tag()).collect::<Vec<u64>>();
+		tags.sort_unstable();
+		let group_size = (tags.len() + targets.len() - 1) / targets.len();
+
+		let mut assigned = HashMap::new();
+		for group in tags.chunks(group_size) {
+			let center = match units.filter(|u| group.contains(&u.tag())).center() {
+				Some(center) => center,
+				None => continue,
+			};
+			let closest = (0..targets.len()).min_by(|a, b| {
+				targets[*a].distance_squared(center)
+					.partial_cmp(&targets[*b].distance_squared(center))
+					.unwrap_or(std::cmp::Ordering::Equal)
+			});
+			let closest = match closest {
+				Some(closest) => targets.remove(closest),
+				None => break,
+			};
+			assigned.extend(group.iter().map(|tag| (*tag, closest)));
+		}
+		assigned
+	}
+}
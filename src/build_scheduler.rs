@@ -38,6 +38,11 @@ impl BuildScheduler {
 			build_queue: queue
 		}
 	}
+
+	/// The current priority of building the given unit, or 0 if it isn't scheduled at all.
+	pub fn unit_priority(&self, unit: UnitTypeId) -> i32 {
+		self.build_queue.get_priority(&Unit(unit)).copied().unwrap_or(0)
+	}
 }
 
 // Doing this in FlourishBot so we can have mutable access to BuildScheduler while viewing
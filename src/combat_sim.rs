@@ -0,0 +1,466 @@
+use rust_sc2::{prelude::*, game_data::TargetType};
+
+use crate::flourish_bot::FlourishBot;
+
+/// What a unit brings to a fight, stripped down to the numbers the simulator needs.
+#[derive(Clone, Debug, Default)]
+pub struct CombatUnit {
+	/// Health plus shields.
+	pub health: f32,
+	pub armor: f32,
+	pub is_flying: bool,
+	pub speed: f32,
+	/// Damage per attack against ground units, with every hit of a multi-hit attack added up.
+	pub ground_damage: f32,
+	pub ground_cooldown: f32,
+	pub ground_range: f32,
+	pub ground_hits: f32,
+	pub air_damage: f32,
+	pub air_cooldown: f32,
+	pub air_range: f32,
+	pub air_hits: f32,
+	/// Share of each attack's damage that also lands on each of a couple of nearby units.
+	pub splash: f32,
+	/// For units that attack by blowing themselves up, how many enemies the explosion catches at
+	/// full damage. Zero for everything else.
+	pub area_targets: usize,
+	/// Minerals plus vespene.
+	pub value: f32,
+}
+
+impl CombatUnit {
+	pub fn can_attack(&self, target: &CombatUnit) -> bool {
+		if target.is_flying {
+			self.air_damage > 0.0
+		} else {
+			self.ground_damage > 0.0
+		}
+	}
+
+	pub fn range_against(&self, target: &CombatUnit) -> f32 {
+		if target.is_flying {
+			self.air_range
+		} else {
+			self.ground_range
+		}
+	}
+
+	/// Damage done by one attack against the target once its armor is taken off every hit.
+	pub fn damage_against(&self, target: &CombatUnit) -> f32 {
+		let (damage, hits) = if target.is_flying {
+			(self.air_damage, self.air_hits)
+		} else {
+			(self.ground_damage, self.ground_hits)
+		};
+		if damage <= 0.0 {
+			return 0.0;
+		}
+		let hits = hits.max(1.0);
+		// SC2 never lets armor take a hit below half a point of damage.
+		(damage / hits - target.armor).max(0.5) * hits
+	}
+
+	/// Damage per second against the target once its armor is taken off every hit.
+	pub fn dps_against(&self, target: &CombatUnit) -> f32 {
+		let cooldown = if target.is_flying { self.air_cooldown } else { self.ground_cooldown };
+		if cooldown <= 0.0 {
+			return 0.0;
+		}
+		self.damage_against(target) / cooldown
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+	Us,
+	Them,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CombatDecision {
+	/// Take the fight.
+	Engage,
+	/// Don't go looking for it, but stand ground if it comes to us.
+	Hold,
+	/// Get out before it starts.
+	Retreat,
+}
+
+#[derive(Clone, Debug)]
+pub struct CombatOutcome {
+	/// Who has units left at the end, or None if neither side could finish the other.
+	pub winner: Option<Side>,
+	pub our_value: f32,
+	pub our_remaining_value: f32,
+	pub enemy_value: f32,
+	pub enemy_remaining_value: f32,
+	/// Seconds the fight lasted.
+	pub duration: f32,
+}
+
+impl CombatOutcome {
+	pub fn our_losses(&self) -> f32 {
+		self.our_value - self.our_remaining_value
+	}
+
+	pub fn enemy_losses(&self) -> f32 {
+		self.enemy_value - self.enemy_remaining_value
+	}
+
+	/// What to do about this fight: take it if we win, back off if we lose and trade badly doing
+	/// it, and otherwise hold our ground.
+	pub fn decision(&self) -> CombatDecision {
+		match self.winner {
+			Some(Side::Us) => CombatDecision::Engage,
+			Some(Side::Them) if self.our_losses() > self.enemy_losses() => CombatDecision::Retreat,
+			_ => CombatDecision::Hold,
+		}
+	}
+}
+
+/// Length of a simulation step, in seconds.
+const STEP: f32 = 0.25;
+/// Fights that go on longer than this are called a draw.
+const MAX_DURATION: f32 = 60.0;
+/// How many extra units each splash attack hits.
+const SPLASH_TARGETS: usize = 2;
+/// How many units a baneling catches in its explosion in a typical fight.
+const BANELING_AREA_TARGETS: usize = 4;
+
+/// Seconds before each unit gets to attack, from having to walk into range of an enemy army with
+/// more range than it.
+fn engage_delays(attackers: &[CombatUnit], defenders: &[CombatUnit]) -> Vec<f32> {
+	attackers
+		.iter()
+		.map(|a| {
+			let their_range = defenders
+				.iter()
+				.filter(|d| d.can_attack(a))
+				.map(|d| d.range_against(a))
+				.fold(0.0, f32::max);
+			let our_range = defenders
+				.iter()
+				.filter(|d| a.can_attack(d))
+				.map(|d| a.range_against(d))
+				.fold(0.0, f32::max);
+			// Immobile units, like static defense, get walked up to instead.
+			if a.speed <= 0.0 {
+				0.0
+			} else {
+				(their_range - our_range).max(0.0) / a.speed
+			}
+		})
+		.collect()
+}
+
+/// Damage one side does to the other over a step, and which attackers blew themselves up doing
+/// it. Every attacker shoots the most hurt enemy it can hit that isn't already going to die this
+/// step, so damage isn't wasted on overkill.
+fn step_damage(
+	attackers: &[CombatUnit],
+	attacker_health: &[f32],
+	delays: &[f32],
+	defenders: &[CombatUnit],
+	defender_health: &[f32],
+	time: f32,
+) -> (Vec<f32>, Vec<usize>) {
+	let mut damage = vec![0.0; defenders.len()];
+	let mut exploded = Vec::new();
+
+	for (i, attacker) in attackers.iter().enumerate() {
+		if attacker_health[i] <= 0.0 || time < delays[i] {
+			continue;
+		}
+
+		let target = (0..defenders.len())
+			.filter(|j| defender_health[*j] - damage[*j] > 0.0 && attacker.can_attack(&defenders[*j]))
+			.min_by(|a, b| {
+				(defender_health[*a] - damage[*a])
+					.partial_cmp(&(defender_health[*b] - damage[*b]))
+					.unwrap_or(std::cmp::Ordering::Equal)
+			});
+		let target = match target {
+			Some(target) => target,
+			None => continue,
+		};
+
+		// One explosion, at full damage to everything it catches, and then the attacker is gone.
+		if attacker.area_targets > 0 {
+			let caught = std::iter::once(target)
+				.chain((0..defenders.len()).filter(|j| *j != target && defender_health[*j] > 0.0 && attacker.can_attack(&defenders[*j])))
+				.take(attacker.area_targets)
+				.collect::<Vec<usize>>();
+			for j in caught {
+				damage[j] += attacker.damage_against(&defenders[j]);
+			}
+			exploded.push(i);
+			continue;
+		}
+
+		damage[target] += attacker.dps_against(&defenders[target]) * STEP;
+
+		if attacker.splash > 0.0 {
+			let splashed = (0..defenders.len())
+				.filter(|j| *j != target && defender_health[*j] > 0.0 && attacker.can_attack(&defenders[*j]))
+				.take(SPLASH_TARGETS)
+				.collect::<Vec<usize>>();
+			for j in splashed {
+				damage[j] += attacker.dps_against(&defenders[j]) * STEP * attacker.splash;
+			}
+		}
+	}
+
+	(damage, exploded)
+}
+
+fn remaining_value(units: &[CombatUnit], health: &[f32]) -> f32 {
+	units
+		.iter()
+		.zip(health)
+		.filter(|(u, h)| **h > 0.0 && u.health > 0.0)
+		.map(|(u, h)| u.value * h / u.health)
+		.sum()
+}
+
+/// Plays out a fight between two groups of units standing in the open, with both sides fighting
+/// until one is wiped out, neither can hurt the other, or it times out.
+pub fn simulate(ours: &[CombatUnit], theirs: &[CombatUnit]) -> CombatOutcome {
+	let mut our_health = ours.iter().map(|u| u.health).collect::<Vec<f32>>();
+	let mut their_health = theirs.iter().map(|u| u.health).collect::<Vec<f32>>();
+	let our_delays = engage_delays(ours, theirs);
+	let their_delays = engage_delays(theirs, ours);
+
+	let alive = |health: &[f32]| health.iter().any(|h| *h > 0.0);
+	let mut time = 0.0;
+
+	while time < MAX_DURATION && alive(&our_health) && alive(&their_health) {
+		let (to_them, our_exploded) = step_damage(ours, &our_health, &our_delays, theirs, &their_health, time);
+		let (to_us, their_exploded) = step_damage(theirs, &their_health, &their_delays, ours, &our_health, time);
+
+		// Nobody left standing can hurt anyone, and nobody is still walking into range.
+		let walking = our_delays.iter().chain(&their_delays).any(|d| *d > time);
+		if !walking && to_them.iter().chain(&to_us).all(|d| *d <= 0.0) {
+			break;
+		}
+
+		for (h, d) in their_health.iter_mut().zip(to_them) {
+			*h -= d;
+		}
+		for (h, d) in our_health.iter_mut().zip(to_us) {
+			*h -= d;
+		}
+		for i in our_exploded {
+			our_health[i] = 0.0;
+		}
+		for i in their_exploded {
+			their_health[i] = 0.0;
+		}
+		time += STEP;
+	}
+
+	let winner = match (alive(&our_health), alive(&their_health)) {
+		(true, false) => Some(Side::Us),
+		(false, true) => Some(Side::Them),
+		_ => None,
+	};
+
+	CombatOutcome {
+		winner,
+		our_value: ours.iter().map(|u| u.value).sum(),
+		our_remaining_value: remaining_value(ours, &our_health),
+		enemy_value: theirs.iter().map(|u| u.value).sum(),
+		enemy_remaining_value: remaining_value(theirs, &their_health),
+		duration: time,
+	}
+}
+
+// Doing this in FlourishBot so unit costs and upgrades can be looked up from the game data.
+impl FlourishBot {
+	/// Rough share of damage splashed onto nearby units, for units whose attacks hit an area.
+	fn splash_share(unit_type: UnitTypeId) -> f32 {
+		match unit_type {
+			UnitTypeId::SiegeTankSieged
+			| UnitTypeId::Colossus
+			| UnitTypeId::Hellion
+			| UnitTypeId::HellionTank
+			| UnitTypeId::LurkerMPBurrowed
+			| UnitTypeId::PlanetaryFortress => 1.0,
+			UnitTypeId::WidowMineBurrowed => 2.0,
+			UnitTypeId::Archon | UnitTypeId::Thor | UnitTypeId::Ultralisk => 0.5,
+			UnitTypeId::Mutalisk => 0.4,
+			_ => 0.0,
+		}
+	}
+
+	/// How many enemies an attack that kills the attacker catches, for units that attack that way.
+	fn area_targets(unit_type: UnitTypeId) -> usize {
+		match unit_type {
+			UnitTypeId::Baneling | UnitTypeId::BanelingBurrowed => BANELING_AREA_TARGETS,
+			_ => 0,
+		}
+	}
+
+	/// The simulator's view of a unit, with its upgrades counted in.
+	pub fn combat_unit(&self, unit: &Unit) -> CombatUnit {
+		let cost = self.get_unit_cost(unit.type_id());
+		let attack_upgrades = unit.attack_upgrade_level() as f32;
+
+		let mut combat_unit = CombatUnit {
+			health: unit.hits().unwrap_or(0) as f32,
+			armor: unit.armor() as f32 + unit.armor_upgrade_level() as f32,
+			is_flying: unit.is_flying(),
+			speed: unit.speed(),
+			splash: Self::splash_share(unit.type_id()),
+			area_targets: Self::area_targets(unit.type_id()),
+			value: (cost.minerals + cost.vespene) as f32,
+			..Default::default()
+		};
+
+		for weapon in unit.weapons() {
+			let hits = weapon.attacks as f32;
+			// Every hit gets a point of damage per upgrade level, which is close enough for most units.
+			let damage = (weapon.damage as f32 + attack_upgrades) * hits;
+			if matches!(weapon.target, TargetType::Ground | TargetType::Any) {
+				combat_unit.ground_damage = damage;
+				combat_unit.ground_cooldown = weapon.speed;
+				combat_unit.ground_range = weapon.range;
+				combat_unit.ground_hits = hits;
+			}
+			if matches!(weapon.target, TargetType::Air | TargetType::Any) {
+				combat_unit.air_damage = damage;
+				combat_unit.air_cooldown = weapon.speed;
+				combat_unit.air_range = weapon.range;
+				combat_unit.air_hits = hits;
+			}
+		}
+
+		combat_unit
+	}
+
+	/// Simulates our units fighting the given enemies.
+	pub fn simulate_fight<'a>(&self, ours: impl Iterator<Item = &'a Unit>, theirs: impl Iterator<Item = &'a Unit>) -> CombatOutcome {
+		let ours = ours.map(|u| self.combat_unit(u)).collect::<Vec<CombatUnit>>();
+		let theirs = theirs.map(|u| self.combat_unit(u)).collect::<Vec<CombatUnit>>();
+		simulate(&ours, &theirs)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn zergling() -> CombatUnit {
+		CombatUnit {
+			health: 35.0,
+			speed: 4.13,
+			ground_damage: 5.0,
+			ground_cooldown: 0.497,
+			ground_range: 0.1,
+			ground_hits: 1.0,
+			value: 25.0,
+			..Default::default()
+		}
+	}
+
+	fn mutalisk() -> CombatUnit {
+		CombatUnit {
+			health: 120.0,
+			armor: 1.0,
+			is_flying: true,
+			speed: 5.6,
+			ground_damage: 9.0,
+			ground_cooldown: 1.09,
+			ground_range: 3.0,
+			ground_hits: 1.0,
+			air_damage: 9.0,
+			air_cooldown: 1.09,
+			air_range: 3.0,
+			air_hits: 1.0,
+			splash: 0.4,
+			value: 200.0,
+			..Default::default()
+		}
+	}
+
+	fn baneling() -> CombatUnit {
+		CombatUnit {
+			health: 30.0,
+			speed: 3.5,
+			ground_damage: 16.0,
+			ground_cooldown: 0.833,
+			ground_range: 0.25,
+			ground_hits: 1.0,
+			area_targets: BANELING_AREA_TARGETS,
+			value: 50.0,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn even_fight_is_a_draw() {
+		let army = vec![zergling(); 8];
+		let outcome = simulate(&army, &army);
+
+		assert_eq!(outcome.winner, None);
+		assert!((outcome.our_losses() - outcome.enemy_losses()).abs() < 1e-3);
+		assert_eq!(outcome.decision(), CombatDecision::Hold);
+	}
+
+	#[test]
+	fn lopsided_fight_has_a_clear_winner() {
+		let big = vec![zergling(); 12];
+		let small = vec![zergling(); 3];
+
+		let winning = simulate(&big, &small);
+		assert_eq!(winning.winner, Some(Side::Us));
+		assert_eq!(winning.decision(), CombatDecision::Engage);
+		assert!(winning.our_losses() < winning.enemy_losses());
+
+		let losing = simulate(&small, &big);
+		assert_eq!(losing.winner, Some(Side::Them));
+		assert_eq!(losing.decision(), CombatDecision::Retreat);
+	}
+
+	#[test]
+	fn units_that_cant_shoot_up_lose_to_air() {
+		let outcome = simulate(&vec![zergling(); 5], &[mutalisk()]);
+
+		assert_eq!(outcome.winner, Some(Side::Them));
+		assert_eq!(outcome.enemy_losses(), 0.0);
+		assert_eq!(outcome.decision(), CombatDecision::Retreat);
+
+		// Neither side can touch the other.
+		let stalemate = simulate(&[zergling()], &[CombatUnit { is_flying: true, ground_damage: 0.0, ..zergling() }]);
+		assert_eq!(stalemate.winner, None);
+		assert_eq!(stalemate.our_losses(), 0.0);
+		assert_eq!(stalemate.decision(), CombatDecision::Hold);
+	}
+
+	#[test]
+	fn empty_sides() {
+		let army = vec![zergling(); 4];
+
+		let nothing_to_fight = simulate(&army, &[]);
+		assert_eq!(nothing_to_fight.winner, Some(Side::Us));
+		assert_eq!(nothing_to_fight.decision(), CombatDecision::Engage);
+		assert_eq!(nothing_to_fight.our_losses(), 0.0);
+
+		let nothing_to_fight_with = simulate(&[], &army);
+		assert_eq!(nothing_to_fight_with.winner, Some(Side::Them));
+		assert_eq!(nothing_to_fight_with.decision(), CombatDecision::Hold);
+
+		assert_eq!(simulate(&[], &[]).winner, None);
+	}
+
+	#[test]
+	fn banelings_explode_once_over_a_capped_area() {
+		let lings = vec![CombatUnit { health: 10.0, ..zergling() }; 8];
+		let outcome = simulate(&[baneling()], &lings);
+
+		// The baneling is gone after its one explosion, which killed at most its cap of lings.
+		assert_eq!(outcome.our_remaining_value, 0.0);
+		let lings_killed = (outcome.enemy_losses() / zergling().value).round() as usize;
+		assert_eq!(lings_killed, BANELING_AREA_TARGETS);
+		assert_eq!(outcome.winner, Some(Side::Them));
+	}
+}
@@ -0,0 +1,210 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+#[derive(Default)]
+pub struct CreepSpread {
+	/// Tumors we've already told to spread. A tumor can only spread once.
+	used_tumors: HashSet<u64>,
+	/// Points along the paths we want covered in creep, in order from our side outwards.
+	path: Vec<Point2>,
+	last_planned: f32,
+	/// Where tumors would just get killed this step, and how far around each point that goes.
+	danger: Vec<(Point2, f32)>,
+	/// Path points without creep that are safe to spread to, as of this step.
+	targets: Vec<Point2>,
+	/// Path points already on creep that are safe to put a tumor on, as of this step.
+	edges: Vec<Point2>,
+}
+
+impl CreepSpread {
+	/// How often, in seconds, the creep paths are worked out again.
+	const REPLAN_DELAY: f32 = 30.0;
+	/// Distance between consecutive points of a creep path.
+	const PATH_STEP: f32 = 3.0;
+}
+
+// Doing this in FlourishBot so we can have mutable access to CreepSpread while viewing units
+// and terrain.
+impl FlourishBot {
+	/// How far from an existing tumor a new one can be placed.
+	const TUMOR_RANGE: f32 = 10.0;
+	/// Creep within this distance of known enemy static defense isn't worth spreading.
+	const CREEP_DEFENSE_AVOID_RADIUS: f32 = 12.0;
+	/// Creep near where enemy units were seen this recently is left alone for now.
+	const CREEP_ENEMY_AVOID_SECONDS: f32 = 20.0;
+	const CREEP_ENEMY_AVOID_RADIUS: f32 = 10.0;
+	/// How far along the attack path, as a share of its length, we want creep.
+	const CREEP_ATTACK_PATH_SHARE: f32 = 0.5;
+	const TUMOR_ENERGY: u32 = 25;
+
+	/// Recomputes the points we want creep on: along the attack path from our natural towards the
+	/// enemy, and from our main to each of our bases and the next planned expansion.
+	fn plan_creep_path(&mut self) {
+		let terrain = match &self.terrain {
+			Some(terrain) => terrain,
+			None => return,
+		};
+
+		let mut path = Vec::new();
+		let mut add_path = |from: Point2, to: Point2, share: f32| {
+			if let Some(field) = terrain.distance_field(to) {
+				let length = terrain.distance_at(&field, from).unwrap_or(0.0) * share;
+				let mut travelled = 0.0;
+				let mut point = from;
+				while travelled < length {
+					point = terrain.step_towards(&field, point, CreepSpread::PATH_STEP);
+					path.push(point);
+					travelled += CreepSpread::PATH_STEP;
+				}
+			}
+		};
+
+		let start = self.start_location;
+		let front = terrain.natural.unwrap_or(start);
+		add_path(front, self.enemy_start, Self::CREEP_ATTACK_PATH_SHARE);
+		for base in self.units.my.townhalls.iter().filter(|t| !t.is_closer(1.0, start)) {
+			add_path(start, base.position(), 1.0);
+		}
+		if let Some(next) = self.expansion_planner.current_choice() {
+			add_path(start, next, 1.0);
+		}
+
+		self.creep_spread.path = path;
+		self.creep_spread.last_planned = self.time;
+	}
+
+	/// Replans the creep paths when they're stale, and works out once for this step where it's
+	/// dangerous to spread and which path points are still to be covered.
+	pub fn update_creep_targets(&mut self) {
+		if self.creep_spread.last_planned + CreepSpread::REPLAN_DELAY < self.time || self.creep_spread.path.is_empty() {
+			self.plan_creep_path();
+		}
+
+		let game_loop = self.state.observation.game_loop();
+		let mut danger = self
+			.surveillance
+			.remembered_enemy_structures()
+			.filter(|s| s.can_attack_ground() || s.is_detector())
+			.map(|s| (s.position(), Self::CREEP_DEFENSE_AVOID_RADIUS))
+			.collect::<Vec<(Point2, f32)>>();
+		danger.extend(
+			self.surveillance
+				.enemies_seen_since(Self::CREEP_ENEMY_AVOID_SECONDS, game_loop)
+				.map(|u| (u.position(), Self::CREEP_ENEMY_AVOID_RADIUS)),
+		);
+		self.creep_spread.danger = danger;
+
+		let (edges, targets): (Vec<Point2>, Vec<Point2>) = self
+			.creep_spread
+			.path
+			.iter()
+			.filter(|p| !self.is_creep_point_dangerous(**p))
+			.partition(|p| self.has_creep(**p));
+		self.creep_spread.edges = edges;
+		self.creep_spread.targets = targets;
+	}
+
+	/// Whether spreading creep to a point would put a tumor somewhere it'll just be killed.
+	fn is_creep_point_dangerous(&self, point: Point2) -> bool {
+		self.creep_spread.danger.iter().any(|(p, radius)| p.is_closer(*radius, point))
+	}
+
+	/// The point along our creep paths closest to `near` that doesn't have creep on it yet and is
+	/// safe to spread to.
+	fn next_creep_target(&self, near: Point2) -> Option<Point2> {
+		self.creep_spread
+			.targets
+			.iter()
+			.min_by(|a, b| {
+				a.distance_squared(near)
+					.partial_cmp(&b.distance_squared(near))
+					.unwrap_or(Ordering::Equal)
+			})
+			.copied()
+	}
+
+	/// Somewhere on creep and visible, within `range` of `from`, as close to `target` as possible.
+	fn tumor_spot(&self, from: Point2, target: Point2, range: f32) -> Option<Point2> {
+		let max = range.min(from.distance(target)).floor() as usize;
+		(1..=max)
+			.rev()
+			.map(|d| from.towards(target, d as f32))
+			.find(|p| self.has_creep(*p) && self.is_visible(*p) && self.is_placeable(*p) && !self.is_creep_point_dangerous(*p))
+	}
+
+	/// Share of the points along our creep paths that are covered in creep, from 0 to 1.
+	pub fn creep_coverage(&self) -> f32 {
+		if self.creep_spread.path.is_empty() {
+			return 0.0;
+		}
+		let covered = self.creep_spread.path.iter().filter(|p| self.has_creep(**p)).count();
+		covered as f32 / self.creep_spread.path.len() as f32
+	}
+
+	/// Tumors that can still spread.
+	pub fn active_tumors(&self) -> Units {
+		self.units
+			.my
+			.structures
+			.filter(|s| s.has_ability(AbilityId::BuildCreepTumorTumor) && !self.creep_spread.used_tumors.contains(&s.tag()))
+	}
+
+	pub fn log_creep_coverage(&self) {
+		println!("[{:>5.0}s] creep coverage {:.0}%", self.time, self.creep_coverage() * 100.0);
+	}
+
+	/// Has every tumor that can still spread place another one towards our creep paths.
+	pub fn spread_creep(&mut self) {
+		let alive = self.units.my.structures.iter().map(|s| s.tag()).collect::<HashSet<u64>>();
+		self.creep_spread.used_tumors.retain(|t| alive.contains(t));
+
+		for tumor in &self.active_tumors() {
+			let spot = self
+				.next_creep_target(tumor.position())
+				.and_then(|target| self.tumor_spot(tumor.position(), target, Self::TUMOR_RANGE));
+			if let Some(spot) = spot {
+				tumor.command(AbilityId::BuildCreepTumorTumor, Target::Pos(spot), false);
+				self.creep_spread.used_tumors.insert(tumor.tag());
+			}
+		}
+	}
+
+	/// Has a creep queen place a tumor at the edge of our creep towards the next uncovered point.
+	/// Returns whether the queen was given an order.
+	pub fn spread_creep_with_queen(&self, queen: &Unit) -> bool {
+		if queen.is_using(AbilityId::BuildCreepTumorQueen) {
+			return true;
+		}
+		if queen.energy().map_or(true, |e| e < Self::TUMOR_ENERGY) {
+			return false;
+		}
+
+		let target = match self.next_creep_target(queen.position()) {
+			Some(target) => target,
+			None => return false,
+		};
+		// Walk back from the target to find where the creep currently ends.
+		let edge = self
+			.creep_spread
+			.edges
+			.iter()
+			.min_by(|a, b| {
+				a.distance_squared(target)
+					.partial_cmp(&b.distance_squared(target))
+					.unwrap_or(Ordering::Equal)
+			})
+			.copied();
+
+		match edge.and_then(|e| self.tumor_spot(e, target, 3.0)) {
+			Some(spot) => {
+				queen.command(AbilityId::BuildCreepTumorQueen, Target::Pos(spot), false);
+				true
+			}
+			None => false,
+		}
+	}
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::{flourish_bot::FlourishBot, squads::SquadRole};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverseerRole {
+	/// Moves with the main army.
+	Army,
+	/// Watches over the townhall with this tag.
+	Base(u64),
+}
+
+#[derive(Default)]
+pub struct Detection {
+	roles: HashMap<u64, OverseerRole>,
+}
+
+impl Detection {
+	pub fn role(&self, overseer: u64) -> Option<OverseerRole> {
+		self.roles.get(&overseer).copied()
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to Detection while viewing units.
+impl FlourishBot {
+	const ARMY_OVERSEERS: usize = 2;
+	const MAX_OVERSEERS: usize = 6;
+	/// How far an overseer or spore reveals cloaked units.
+	const DETECTION_RANGE: f32 = 11.0;
+	/// Enemies we can't see this close to a squad keep it out of the fight.
+	const CLOAK_AVOID_RADIUS: f32 = 15.0;
+	const CLOAK_AVOID_SECONDS: f32 = 5.0;
+	/// How far behind the army's center its overseers stay.
+	const OVERSEER_TRAIL_DISTANCE: f32 = 3.0;
+
+	fn wanted_overseers(&self) -> usize {
+		if !self.surveillance.has_cloak_threat() {
+			return 0;
+		}
+		(Self::ARMY_OVERSEERS + self.units.my.townhalls.ready().len()).min(Self::MAX_OVERSEERS)
+	}
+
+	/// Morphs overlords into overseers, the ones nearest home first, until we have as many as the
+	/// cloak threat calls for.
+	pub fn morph_overseers(&mut self) {
+		let has_lair = self.has_prereq(UnitTypeId::Lair) || self.has_prereq(UnitTypeId::Hive);
+		if !has_lair {
+			return;
+		}
+
+		let mut overseers = self.counter().all().count(UnitTypeId::Overseer)
+			+ self.counter().all().count(UnitTypeId::OverlordCocoon);
+		let mut overlords = self.units.my.units.of_type(UnitTypeId::Overlord);
+		let start = self.start_location;
+		overlords.sort(|o| o.distance_squared(start) as u32);
+
+		for overlord in &overlords {
+			if overseers >= self.wanted_overseers() || !self.can_afford(UnitTypeId::Overseer, false) {
+				break;
+			}
+			overlord.use_ability(AbilityId::MorphOverseer, false);
+			self.subtract_resources(UnitTypeId::Overseer, false);
+			overseers += 1;
+		}
+	}
+
+	/// Gives overseers a place to be: the army first, then one over each base.
+	fn assign_overseer_roles(&mut self) {
+		let overseers = self.units.my.units.of_type(UnitTypeId::Overseer);
+		let townhalls = self.units.my.townhalls.ready();
+
+		self.detection.roles.retain(|tag, role| {
+			overseers.get(*tag).is_some()
+				&& match role {
+					OverseerRole::Base(base) => townhalls.get(*base).is_some(),
+					OverseerRole::Army => true,
+				}
+		});
+
+		for overseer in &overseers {
+			if self.detection.role(overseer.tag()).is_some() {
+				continue;
+			}
+
+			let with_army = self.detection.roles.values().filter(|r| **r == OverseerRole::Army).count();
+			let watched = self
+				.detection
+				.roles
+				.values()
+				.filter_map(|r| match r {
+					OverseerRole::Base(base) => Some(*base),
+					_ => None,
+				})
+				.collect::<Vec<u64>>();
+			let unwatched = townhalls.iter().filter(|t| !watched.contains(&t.tag())).closest(overseer).map(|t| t.tag());
+
+			let role = match unwatched {
+				Some(base) if with_army >= Self::ARMY_OVERSEERS => OverseerRole::Base(base),
+				_ => OverseerRole::Army,
+			};
+			self.detection.roles.insert(overseer.tag(), role);
+		}
+	}
+
+	/// Whether an overseer or spore would reveal cloaked units at a point.
+	pub fn has_detection_at(&self, point: Point2) -> bool {
+		self.units
+			.my
+			.all
+			.iter()
+			.filter(|u| u.is_detector() && u.is_ready())
+			.any(|u| u.is_closer(Self::DETECTION_RANGE, point))
+	}
+
+	/// Whether there are cloaked or burrowed enemies that can hurt us near a point and that we
+	/// can't reveal, so our units there would be fighting something they can't shoot. Observers
+	/// and other harmless cloaked units don't count.
+	pub fn has_undetected_threat_near(&self, point: Point2) -> bool {
+		let hidden = self
+			.units
+			.enemy
+			.all
+			.iter()
+			.any(|e| {
+				e.is_cloaked()
+					&& !e.is_revealed()
+					&& (e.can_attack() || e.type_id() == UnitTypeId::WidowMineBurrowed)
+					&& e.is_closer(Self::CLOAK_AVOID_RADIUS, point)
+			})
+			|| self.surveillance.unseen_attack_near(
+				point,
+				Self::CLOAK_AVOID_RADIUS,
+				Self::CLOAK_AVOID_SECONDS,
+				self.state.observation.game_loop(),
+			);
+
+		hidden && !self.has_detection_at(point)
+	}
+
+	/// Keeps overseers with the army or over their base, out of range of anything that can shoot
+	/// them down.
+	pub fn overseer_micro(&mut self) {
+		self.morph_overseers();
+		self.assign_overseer_roles();
+
+		let army = self.army_units();
+		let army_center = self.army.squad(SquadRole::MainArmy).and_then(|s| s.units(&army).center());
+		let home = self.start_location;
+
+		for overseer in &self.units.my.units.of_type(UnitTypeId::Overseer) {
+			let threat = self
+				.units
+				.enemy
+				.all
+				.iter()
+				.filter(|e| e.can_attack_air() && e.is_closer(e.air_range() + 2.0, overseer))
+				.closest(overseer);
+			if let Some(threat) = threat {
+				overseer.move_to(Target::Pos(overseer.position().towards(threat.position(), -2.0)), false);
+				continue;
+			}
+
+			let position = match self.detection.role(overseer.tag()) {
+				Some(OverseerRole::Army) => army_center.map(|c| c.towards(home, Self::OVERSEER_TRAIL_DISTANCE)),
+				Some(OverseerRole::Base(base)) => self.units.my.townhalls.get(base).map(|t| {
+					let minerals = self.units.mineral_fields.closer(11.0, t);
+					minerals.center().map_or(t.position(), |c| t.position().towards(c, 3.0))
+				}),
+				None => None,
+			};
+			if let Some(position) = position.filter(|p| !overseer.is_closer(2.0, *p)) {
+				overseer.move_to(Target::Pos(position), false);
+			}
+		}
+	}
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use crate::flourish_bot::FlourishBot;
+
+/// A snapshot of the game's running resource totals at a point in time.
+struct ResourceSample {
+	time: f32,
+	collected_minerals: f32,
+	collected_vespene: f32,
+	spent_minerals: f32,
+	spent_vespene: f32,
+}
+
+#[derive(Default)]
+pub struct EconomyStats {
+	samples: VecDeque<ResourceSample>,
+	last_time: f32,
+	mineral_income: f32,
+	vespene_income: f32,
+	mineral_spending: f32,
+	vespene_spending: f32,
+	unspent_time: f32,
+	supply_blocked_time: f32,
+}
+
+impl EconomyStats {
+	/// How many seconds of samples the rolling rates are averaged over.
+	const WINDOW: f32 = 60.0;
+	/// Combined bank above which we count ourselves as floating resources.
+	pub const FLOAT_THRESHOLD: u32 = 500;
+
+	/// Minerals mined per minute, averaged over the last minute.
+	pub fn mineral_income(&self) -> f32 {
+		self.mineral_income
+	}
+
+	/// Vespene mined per minute, averaged over the last minute.
+	pub fn vespene_income(&self) -> f32 {
+		self.vespene_income
+	}
+
+	/// Minerals spent per minute, averaged over the last minute.
+	pub fn mineral_spending(&self) -> f32 {
+		self.mineral_spending
+	}
+
+	/// Vespene spent per minute, averaged over the last minute.
+	pub fn vespene_spending(&self) -> f32 {
+		self.vespene_spending
+	}
+
+	/// Total seconds this game that we've been sitting on more than FLOAT_THRESHOLD resources.
+	pub fn unspent_time(&self) -> f32 {
+		self.unspent_time
+	}
+
+	/// Total seconds this game that we've been supply blocked.
+	pub fn supply_blocked_time(&self) -> f32 {
+		self.supply_blocked_time
+	}
+
+	fn record(&mut self, sample: ResourceSample, floating: bool, supply_blocked: bool) {
+		let elapsed = (sample.time - self.last_time).max(0.0);
+		self.last_time = sample.time;
+		if floating {
+			self.unspent_time += elapsed;
+		}
+		if supply_blocked {
+			self.supply_blocked_time += elapsed;
+		}
+
+		while self.samples.front().map_or(false, |s| s.time + Self::WINDOW < sample.time) {
+			self.samples.pop_front();
+		}
+
+		if let Some(oldest) = self.samples.front() {
+			let minutes = (sample.time - oldest.time) / 60.0;
+			if minutes > 0.0 {
+				self.mineral_income = (sample.collected_minerals - oldest.collected_minerals) / minutes;
+				self.vespene_income = (sample.collected_vespene - oldest.collected_vespene) / minutes;
+				self.mineral_spending = (sample.spent_minerals - oldest.spent_minerals) / minutes;
+				self.vespene_spending = (sample.spent_vespene - oldest.spent_vespene) / minutes;
+			}
+		}
+
+		self.samples.push_back(sample);
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to the stats while viewing the
+// observation.
+impl FlourishBot {
+	/// Adds the current resource totals to the rolling income and spending statistics.
+	pub fn update_economy_stats(&mut self) {
+		let score = &self.state.observation.score;
+		let sample = ResourceSample {
+			time: self.time,
+			collected_minerals: score.collected_minerals,
+			collected_vespene: score.collected_vespene,
+			spent_minerals: score.spent_minerals,
+			spent_vespene: score.spent_vespene,
+		};
+
+		let floating = self.minerals + self.vespene > EconomyStats::FLOAT_THRESHOLD;
+		let supply_blocked = self.supply_left == 0 && self.supply_cap < 200;
+
+		self.economy.record(sample, floating, supply_blocked);
+	}
+
+	/// Whether we have more resources banked than we're likely to spend soon.
+	pub fn is_floating_resources(&self) -> bool {
+		self.minerals + self.vespene > EconomyStats::FLOAT_THRESHOLD
+			&& self.economy.mineral_spending() < self.economy.mineral_income()
+	}
+
+	pub fn log_economy_stats(&self) {
+		println!(
+			"[{:>5.0}s] income {:.0}m/{:.0}g per min, spending {:.0}m/{:.0}g per min, bank {}m/{}g, unspent {:.0}s, supply blocked {:.0}s",
+			self.time,
+			self.economy.mineral_income(),
+			self.economy.vespene_income(),
+			self.economy.mineral_spending(),
+			self.economy.vespene_spending(),
+			self.minerals,
+			self.vespene,
+			self.economy.unspent_time(),
+			self.economy.supply_blocked_time(),
+		);
+	}
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+#[derive(Default)]
+pub struct Evacuation {
+	/// Drones that have fled their base, mapped to the tag of the base they fled from.
+	evacuees: HashMap<u64, u64>,
+}
+
+impl Evacuation {
+	/// Whether the given drone has fled its base and is waiting to go back.
+	pub fn is_evacuated(&self, tag: u64) -> bool {
+		self.evacuees.contains_key(&tag)
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to Evacuation while viewing
+// units.
+impl FlourishBot {
+	/// How close to a townhall an enemy has to be to threaten its mineral line.
+	const HARASSMENT_RADIUS: f32 = 14.0;
+	/// Fraction of a base's drones we're willing to lose before help arrives.
+	const ACCEPTABLE_DRONE_LOSSES: f32 = 0.25;
+
+	/// Damage per second an enemy unit does to drones. Widow mines don't have a regular weapon
+	/// so they get a rough estimate of their burst instead.
+	fn harass_dps(enemy: &Unit) -> f32 {
+		match enemy.type_id() {
+			UnitTypeId::WidowMine | UnitTypeId::WidowMineBurrowed => 30.0,
+			_ => enemy.ground_dps(),
+		}
+	}
+
+	/// Seconds until some unit of ours that can fight back reaches the given threats. Static
+	/// defense already in range counts as arriving immediately.
+	fn defense_arrival_time(&self, threats: &Units) -> f32 {
+		self.units
+			.my
+			.all
+			.iter()
+			.filter(|u| !u.is_worker() && u.is_ready())
+			.filter_map(|u| {
+				let target = threats.iter().filter(|t| u.can_attack_unit(t)).closest(u)?;
+				let range = if target.is_flying() { u.air_range() } else { u.ground_range() };
+				let distance = (u.distance(target) - range).max(0.0);
+				if distance == 0.0 {
+					Some(0.0)
+				} else if u.speed() > 0.0 {
+					Some(distance / u.speed())
+				} else {
+					None
+				}
+			})
+			.fold(f32::INFINITY, f32::min)
+	}
+
+	/// Moves drones away from bases under harassment they can't survive until defense arrives,
+	/// and sends them back once the harassment has ended.
+	pub fn evacuate_harassed_bases(&mut self) {
+		let bases = self.units.my.townhalls.ready();
+		let workers = self.units.my.workers.clone();
+		let mut threatened_bases = Vec::new();
+
+		for base in &bases {
+			let threats = self.units.enemy.units.filter(|e| {
+				!e.is_worker() && e.is_closer(Self::HARASSMENT_RADIUS, base) && Self::harass_dps(e) > 0.0
+			});
+			if threats.is_empty() {
+				continue;
+			}
+
+			let local_workers = workers.filter(|u| {
+				u.is_closer(Self::HARASSMENT_RADIUS, base) && !self.worker_defense.is_defending(u.tag())
+			});
+			let threat_dps = threats.iter().map(Self::harass_dps).sum::<f32>();
+			let drone_health = local_workers.iter().map(|u| u.hits().unwrap_or(0) as f32).sum::<f32>();
+			let time_to_lose_drones = drone_health * Self::ACCEPTABLE_DRONE_LOSSES / threat_dps;
+
+			if time_to_lose_drones < self.defense_arrival_time(&threats) {
+				threatened_bases.push((base.clone(), threats, local_workers));
+			}
+		}
+
+		let threatened_tags = threatened_bases.iter().map(|(b, _, _)| b.tag()).collect::<Vec<u64>>();
+
+		// Send drones home from bases that are safe again.
+		let mineral_fields = self.units.mineral_fields.clone();
+		self.evacuation.evacuees.retain(|worker_tag, base_tag| {
+			if threatened_tags.contains(base_tag) {
+				return true;
+			}
+			if let (Some(worker), Some(base)) = (workers.get(*worker_tag), bases.get(*base_tag)) {
+				if let Some(mineral) = mineral_fields.closer(11.0, base).closest(worker) {
+					worker.gather(mineral.tag(), false);
+				}
+			}
+			false
+		});
+
+		for (base, threats, local_workers) in &threatened_bases {
+			let threat_center = threats.center().unwrap();
+			// Flee to the closest base that isn't itself under threat, mineral walking through
+			// anything in the way.
+			let refuge = bases
+				.iter()
+				.filter(|b| !threatened_tags.contains(&b.tag()))
+				.closest(base);
+			let refuge_mineral = refuge.and_then(|r| mineral_fields.closer(11.0, r).closest(r).map(|m| m.tag()));
+
+			let fleeing = local_workers.filter(|u| !self.evacuation.is_evacuated(u.tag()));
+			for u in &fleeing {
+				match refuge_mineral {
+					Some(mineral) => u.gather(mineral, false),
+					None => u.move_to(Target::Pos(base.position().towards(threat_center, -Self::HARASSMENT_RADIUS)), false),
+				}
+				self.evacuation.evacuees.insert(u.tag(), base.tag());
+			}
+		}
+	}
+}
@@ -0,0 +1,241 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+use crate::terrain::DistanceField;
+
+#[derive(Default)]
+pub struct ExpansionPlanner {
+	/// Where we last decided to expand to, if anywhere.
+	choice: Option<Point2>,
+	last_evaluated: f32,
+}
+
+impl ExpansionPlanner {
+	/// How often, in seconds, the expansion candidates get rescored.
+	const EVALUATION_DELAY: f32 = 5.0;
+
+	/// Where we last decided to expand to, without rescoring.
+	pub fn current_choice(&self) -> Option<Point2> {
+		self.choice
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to ExpansionPlanner while viewing
+// units and terrain.
+impl FlourishBot {
+	/// Enemies this close to an expansion location make it contested.
+	const CONTESTED_RADIUS: f32 = 12.0;
+	/// How far from an expansion we look for recently seen enemy army units.
+	const ENEMY_PRESENCE_RADIUS: f32 = 25.0;
+	/// How long ago an enemy army sighting still counts against an expansion.
+	const ENEMY_PRESENCE_SECONDS: f32 = 45.0;
+	/// If every uncontested expansion scores this much worse than the best contested one, we wait
+	/// for the best one to clear instead of diverting.
+	const MAX_DIVERSION_COST: f32 = 40.0;
+	/// Projected share of mining spots filled at which we want another base.
+	const EXPAND_SATURATION: f32 = 0.8;
+	/// Banked minerals at which we expand regardless of saturation, as long as it's safe.
+	const EXPAND_BANK: u32 = 600;
+	/// Banked minerals at which a larva-starved, saturated economy gets a macro hatchery.
+	const MACRO_HATCH_BANK: u32 = 400;
+	/// How many mining bases we want per macro hatchery at most.
+	const BASES_PER_MACRO_HATCH: usize = 3;
+
+	/// Scores an expansion location; higher is better. `own_fields` are ground distance fields
+	/// from each of our bases. Returns None if we can't walk there.
+	fn score_expansion(&self, location: Point2, own_fields: &[Rc<DistanceField>]) -> Option<f32> {
+		let own_distance = match &self.terrain {
+			Some(terrain) => own_fields
+				.iter()
+				.filter_map(|field| terrain.distance_at(field, location))
+				.fold(f32::INFINITY, f32::min),
+			None => self
+				.units
+				.my
+				.townhalls
+				.iter()
+				.map(|b| b.distance(location))
+				.fold(f32::INFINITY, f32::min),
+		};
+		if !own_distance.is_finite() {
+			return None;
+		}
+
+		let enemy_distance = self
+			.units
+			.enemy
+			.townhalls
+			.iter()
+			.map(|t| t.position())
+			.chain(std::iter::once(self.enemy_start))
+			.map(|p| p.distance(location))
+			.fold(f32::INFINITY, f32::min);
+
+		let resources = self
+			.units
+			.mineral_fields
+			.closer(11.0, location)
+			.iter()
+			.map(|m| m.mineral_contents().unwrap_or(0))
+			.chain(self.units.vespene_geysers.closer(11.0, location).iter().map(|g| g.vespene_contents().unwrap_or(0)))
+			.sum::<u32>();
+
+		let recent_enemy_supply = self.surveillance.enemy_supply_seen_near(
+			location,
+			Self::ENEMY_PRESENCE_RADIUS,
+			Self::ENEMY_PRESENCE_SECONDS,
+			self.state.observation.game_loop(),
+		);
+
+		Some(
+			enemy_distance * 0.5 - own_distance
+				+ resources as f32 / 500.0
+				- recent_enemy_supply * 5.0,
+		)
+	}
+
+	/// Whether the enemy currently has units or buildings at an expansion location.
+	fn is_expansion_contested(&self, location: Point2) -> bool {
+		self.units
+			.enemy
+			.all
+			.iter()
+			.any(|e| !e.is_worker() && e.is_closer(Self::CONTESTED_RADIUS, location))
+	}
+
+	/// Drones we have or are making, over the drones our bases (including ones still being built)
+	/// and all their geysers could use.
+	pub fn projected_saturation(&self) -> f32 {
+		let drones = self.counter().all().count(UnitTypeId::Drone) as f32;
+
+		let mining_bases = self
+			.units
+			.my
+			.townhalls
+			.iter()
+			.filter(|t| self.units.mineral_fields.iter().any(|m| m.is_closer(11.0, *t)))
+			.collect::<Vec<&Unit>>();
+		let base_spots = mining_bases
+			.iter()
+			.map(|t| match t.ideal_harvesters() {
+				Some(ideal) if t.is_ready() => ideal,
+				// Not finished yet; assume the usual 8 mineral patches.
+				_ => 16,
+			})
+			.sum::<u32>();
+		// Every geyser at a mining base counts, taken or not, since we'll want drones on it before
+		// long and shouldn't expand before then.
+		let gas_spots = mining_bases
+			.iter()
+			.map(|t| {
+				self.units
+					.vespene_geysers
+					.iter()
+					.filter(|g| g.is_closer(11.0, *t) && g.vespene_contents().map_or(true, |v| v > 0))
+					.count() as u32
+					* 3
+			})
+			.sum::<u32>();
+
+		let spots = (base_spots + gas_spots) as f32;
+		if spots == 0.0 {
+			f32::INFINITY
+		} else {
+			drones / spots
+		}
+	}
+
+	/// Whether our bases are safe enough to spend 300 minerals on a new one.
+	fn is_safe_to_expand(&self) -> bool {
+		let under_attack = self.units.my.townhalls.iter().any(|b| self.is_base_threatened(b));
+		!under_attack || self.supply_army >= self.surveillance.rounded_enemy_supply()
+	}
+
+	/// Whether we should build another hatchery at an expansion right now, based on how saturated
+	/// our existing bases will be, how much we have banked, and how safe we are.
+	pub fn should_expand(&self) -> bool {
+		if !self.is_safe_to_expand() {
+			return false;
+		}
+
+		// A drone already on its way to build one would just be followed by another to the same spot.
+		if self.counter().ordered().count(UnitTypeId::Hatchery) > 0 {
+			return false;
+		}
+
+		// One expansion at a time unless we're floating.
+		let expanding = self.units.my.townhalls.iter().any(|t| !t.is_ready());
+		if expanding {
+			return self.minerals >= 2 * Self::EXPAND_BANK;
+		}
+
+		self.projected_saturation() >= Self::EXPAND_SATURATION || self.minerals >= Self::EXPAND_BANK
+	}
+
+	/// Whether we should build a hatchery in our main purely for the larva: we've run out of larva,
+	/// our bases are saturated and the minerals are piling up.
+	pub fn wants_macro_hatch(&self) -> bool {
+		let mining_bases = self
+			.units
+			.my
+			.townhalls
+			.iter()
+			.filter(|t| self.units.mineral_fields.iter().any(|m| m.is_closer(11.0, *t)))
+			.count();
+		let macro_hatches = self.units.my.townhalls.len() - mining_bases;
+
+		self.units.my.larvas.is_empty()
+			&& self.counter().ordered().count(UnitTypeId::Hatchery) == 0
+			&& self.minerals >= Self::MACRO_HATCH_BANK
+			&& self.is_floating_resources()
+			&& self.projected_saturation() >= 1.0
+			&& macro_hatches * Self::BASES_PER_MACRO_HATCH < mining_bases
+	}
+
+	/// Picks the best free expansion by ground distance from our bases, distance from the enemy,
+	/// resources left and recent enemy army sightings. Returns None if there's nowhere good to
+	/// expand to right now, including when the best location is contested and the alternatives
+	/// are much worse.
+	pub fn choose_expansion(&mut self) -> Option<Point2> {
+		if self.expansion_planner.last_evaluated + ExpansionPlanner::EVALUATION_DELAY > self.time {
+			// Someone may have taken or moved onto the spot since we picked it.
+			let still_free = self.expansion_planner.choice.map_or(true, |choice| {
+				self.free_expansions().any(|e| e.loc == choice) && !self.is_expansion_contested(choice)
+			});
+			if still_free {
+				return self.expansion_planner.choice;
+			}
+		}
+
+		let own_fields = match &self.terrain {
+			Some(terrain) => self
+				.units
+				.my
+				.townhalls
+				.iter()
+				.filter_map(|b| terrain.distance_field(b.position()))
+				.collect::<Vec<Rc<DistanceField>>>(),
+			None => Vec::new(),
+		};
+
+		let mut candidates = self
+			.free_expansions()
+			.filter_map(|e| self.score_expansion(e.loc, &own_fields).map(|score| (e.loc, score)))
+			.collect::<Vec<(Point2, f32)>>();
+		candidates.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+
+		let best_score = candidates.first().map(|(_, score)| *score);
+		let choice = candidates
+			.iter()
+			.find(|(location, _)| !self.is_expansion_contested(*location))
+			.filter(|(_, score)| best_score.map_or(true, |best| best - score < Self::MAX_DIVERSION_COST))
+			.map(|(location, _)| *location);
+
+		self.expansion_planner.choice = choice;
+		self.expansion_planner.last_evaluated = self.time;
+		choice
+	}
+}
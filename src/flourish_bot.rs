@@ -3,21 +3,48 @@ use std::cmp::Ordering;
 use rust_sc2::prelude::*;
 
 use crate::prereqs::upgrade_prereqs;
+use crate::queens::QueenManager;
+use crate::squads::ArmyManager;
+use crate::base_hunt::BaseHunt;
 use crate::build_scheduler::BuildScheduler;
+use crate::creep::CreepSpread;
+use crate::detection::Detection;
+use crate::economy::EconomyStats;
+use crate::evacuation::Evacuation;
+use crate::injects::InjectTracker;
+use crate::expansion::ExpansionPlanner;
+use crate::static_defense::StaticDefensePlan;
 use crate::surveillance::Surveillance;
+use crate::terrain::TerrainAnalysis;
+use crate::layout::BaseLayout;
+use crate::worker_defense::WorkerDefense;
+use crate::worker_transfer::WorkerTransfer;
 
 #[bot]
 pub struct FlourishBot {
 	last_loop_distributed: u32,
 	last_loop_upgraded: u32,
 	last_debug_messages: f32,
-	attacking: bool,
 	has_enough_gas: bool,
-	has_way_too_much_gas: bool,
+	target_gas_workers: usize,
 	has_enough_workers_for_gas: bool,
 	upgrades_to_research: Vec<UpgradeId>,
 	pub build_scheduler: BuildScheduler,
 	pub surveillance: Surveillance,
+	pub economy: EconomyStats,
+	pub worker_defense: WorkerDefense,
+	pub evacuation: Evacuation,
+	pub terrain: Option<TerrainAnalysis>,
+	pub layouts: Vec<BaseLayout>,
+	pub expansion_planner: ExpansionPlanner,
+	pub static_defense: StaticDefensePlan,
+	pub queens: QueenManager,
+	pub creep_spread: CreepSpread,
+	pub inject_tracker: InjectTracker,
+	pub army: ArmyManager,
+	pub base_hunt: BaseHunt,
+	pub detection: Detection,
+	pub worker_transfer: WorkerTransfer,
 }
 
 impl Default for FlourishBot {
@@ -30,6 +57,7 @@ impl Default for FlourishBot {
 			UpgradeId::ZergGroundArmorsLevel1,
 			UpgradeId::ZergFlyerArmorsLevel1,
 			UpgradeId::GlialReconstitution,
+			UpgradeId::Burrow,
 			UpgradeId::TunnelingClaws,
 			UpgradeId::ZergMeleeWeaponsLevel2,
 			UpgradeId::ZergMissileWeaponsLevel2,
@@ -48,11 +76,24 @@ impl Default for FlourishBot {
 			last_loop_distributed: Default::default(),
 			last_loop_upgraded: Default::default(),
 			last_debug_messages: Default::default(),
-			attacking: Default::default(),
 			has_enough_gas: Default::default(),
-			has_way_too_much_gas: Default::default(),
+			target_gas_workers: Default::default(),
 			has_enough_workers_for_gas: Default::default(),
 			surveillance: Default::default(),
+			economy: Default::default(),
+			worker_defense: Default::default(),
+			evacuation: Default::default(),
+			terrain: None,
+			layouts: Vec::new(),
+			expansion_planner: Default::default(),
+			static_defense: Default::default(),
+			queens: Default::default(),
+			creep_spread: Default::default(),
+			inject_tracker: Default::default(),
+			army: Default::default(),
+			base_hunt: Default::default(),
+			detection: Default::default(),
+			worker_transfer: Default::default(),
 			build_scheduler: BuildScheduler::initialise(&upgrades_to_research),
 			upgrades_to_research
 		}
@@ -69,6 +110,9 @@ impl Player for FlourishBot {
 	}
 
 	fn on_start(&mut self) -> SC2Result<()> {
+		self.analyse_terrain();
+		self.plan_layouts();
+
 		// Setting rallypoint for hatchery
 		if let Some(townhall) = self.units.my.townhalls.first() {
 			townhall.command(AbilityId::RallyWorkers, Target::Pos(self.start_center), false);
@@ -96,9 +140,12 @@ impl Player for FlourishBot {
 	fn on_step(&mut self, _iteration: usize) -> SC2Result<()> {
 		self.global_data();
 		self.debug_messages();
+		self.defend_with_workers();
+		self.evacuate_harassed_bases();
 		self.distribute_workers();
 		self.upgrades();
 		self.build();
+		self.static_defense();
 		self.order_units();
 		self.execute_micro();
 		
@@ -108,6 +155,8 @@ impl Player for FlourishBot {
 	fn on_event(&mut self, event: Event) -> SC2Result<()> {
 		match event {
 			Event::UnitDestroyed(tag, team) => {
+				self.queens.queen_died(tag);
+				self.army.remove_unit(tag);
 				if let Some(team) = team {
 					if team.is_enemy() {
 						self.surveillance.observed_enemy_unit_die(self.units.enemy.all.get(tag).unwrap().clone());
@@ -116,7 +165,11 @@ impl Player for FlourishBot {
 			},
 			Event::UnitCreated(_) => { },
 			Event::ConstructionStarted(_) => { },
-			Event::ConstructionComplete(_) => { },
+			Event::ConstructionComplete(tag) => {
+				if self.units.my.townhalls.get(tag).is_some() {
+					self.transfer_workers_to_new_base(tag);
+				}
+			},
 			Event::RandomRaceDetected(_) => { },
 		}
 
@@ -128,12 +181,78 @@ impl FlourishBot {
 	const DEBUG_MESSAGE_DELAY: f32 = 60.0;
 	const DISTRIBUTION_DELAY: u32 = 8;
 	const UPGRADE_DELAY: u32 = 12;
+	/// How many seconds ahead we look at planned spending when deciding how many drones to put on gas.
+	const GAS_PLANNING_HORIZON: f32 = 60.0;
+	/// Roughly how much vespene one drone mines in a minute.
+	const VESPENE_PER_WORKER_MINUTE: f32 = 53.0;
+	/// How far the vespene bank has to get ahead of the plan before drones come off gas.
+	const GAS_HYSTERESIS: u32 = 100;
 
 	fn global_data(&mut self) {
 		self.has_enough_gas = self.vespene > 200 && self.vespene > self.minerals / 3;
-		self.has_way_too_much_gas = self.has_enough_gas && self.vespene > 2*self.minerals;
 		self.has_enough_workers_for_gas = self.counter().count(UnitTypeId::Drone) > 10;
-		self.update_enemy_units()
+		self.update_enemy_units();
+		self.update_economy_stats();
+		self.track_missed_injects();
+		self.update_base_hunt();
+		self.restore_worker_rallies();
+		self.target_gas_workers = self.compute_target_gas_workers();
+	}
+
+	/// Vespene needed by everything we expect to start in the next GAS_PLANNING_HORIZON seconds.
+	fn planned_vespene(&self) -> u32 {
+		let mut planned = 0;
+
+		for upgrade in &self.upgrades_to_research {
+			if self.has_upgrade(*upgrade) || self.is_ordered_upgrade(*upgrade) {
+				continue;
+			}
+			if let Some((structure_type, prereq_structures, prereq_upgrades)) = upgrade_prereqs(*upgrade) {
+				let prereq_upgrades_started = prereq_upgrades
+					.iter()
+					.all(|u| self.has_upgrade(*u) || self.is_ordered_upgrade(*u));
+				if self.has_prereq(structure_type) && self.has_prereqs(prereq_structures, vec![]) && prereq_upgrades_started {
+					planned += self.get_upgrade_cost(*upgrade).vespene;
+				}
+			}
+		}
+
+		let lair = UnitTypeId::Lair;
+		let no_lair = self.counter().all().count(lair) + self.counter().all().count(UnitTypeId::Hive) == 0;
+		if no_lair && self.time + Self::GAS_PLANNING_HORIZON > 7.0 * 60.0 {
+			planned += self.get_unit_cost(lair).vespene;
+		}
+
+		planned
+	}
+
+	/// How many drones in total should be mining gas for us to afford our planned spending.
+	fn compute_target_gas_workers(&self) -> usize {
+		if !self.has_enough_workers_for_gas {
+			return 0;
+		}
+
+		let planned = self.planned_vespene();
+		let shortfall = planned.saturating_sub(self.vespene) as f32;
+		let per_worker = Self::VESPENE_PER_WORKER_MINUTE * Self::GAS_PLANNING_HORIZON / 60.0;
+		let mut needed = (shortfall / per_worker).ceil() as usize;
+
+		// Don't pull drones off gas the moment the bank covers the plan, only once it's comfortably
+		// ahead, so they aren't moved back and forth every time it's just covered.
+		if needed < self.target_gas_workers && self.vespene < planned + Self::GAS_HYSTERESIS {
+			needed = self.target_gas_workers;
+		}
+
+		let max_workers = 3 * self
+			.units
+			.my
+			.gas_buildings
+			.iter()
+			.ready()
+			.filter(|g| g.vespene_contents().map_or(false, |v| v > 0))
+			.count();
+
+		needed.min(max_workers)
 	}
 
 	fn debug_messages(&mut self) {
@@ -143,13 +262,17 @@ impl FlourishBot {
 			return;
 		}
 		*last_debug_messages = time;
+
+		self.log_economy_stats();
+		self.log_missed_injects();
+		self.log_creep_coverage();
 	}
 
 	fn distribute_workers(&mut self) {
 		if self.units.my.workers.is_empty() {
 			return;
 		}
-		let mut idle_workers = self.units.my.workers.idle();
+		let mut idle_workers = self.units.my.workers.idle().filter(|u| !self.is_worker_reserved(u.tag()));
 		let bases = self.units.my.townhalls.ready();
 
 		// Check distribution delay if there aren't any idle workers
@@ -184,30 +307,36 @@ impl FlourishBot {
 			}
 		}
 
-		// Distributing gas workers
-		let target_gas_workers: usize = if !self.has_enough_workers_for_gas { 0 } else if self.has_way_too_much_gas { 1 } else if self.has_enough_gas { 2 } else { 3 };
+		// Distributing gas workers, filling each extractor in turn until we reach the target
+		let mut unassigned_gas_workers = self.target_gas_workers;
 
-		self.units.my.gas_buildings.iter().ready().for_each(|gas| {
-			let ideal_harvesters = gas.ideal_harvesters().unwrap() as usize;
+		for gas in self.units.my.gas_buildings.iter().ready() {
+			// Depleted extractors get no workers, so anyone still on them goes back to minerals.
+			let target_gas_workers = if gas.vespene_contents() == Some(0) {
+				0
+			} else {
+				unassigned_gas_workers.min(gas.ideal_harvesters().unwrap() as usize)
+			};
+			unassigned_gas_workers -= target_gas_workers;
 			let assigned_harvesters = gas.assigned_harvesters().unwrap() as usize;
 
-			match gas.assigned_harvesters().cmp(&Some(target_gas_workers as u32)) {
+			match assigned_harvesters.cmp(&target_gas_workers) {
 				Ordering::Less => {
 					// If there are less than the desired number of gas workers, workers
 					// can be stolen from anywhere to put in gas
 					idle_workers.extend(self.units.my.workers.filter(|u| {
-						u.target_tag()
+						!self.is_worker_reserved(u.tag()) && u.target_tag()
 							.map_or(false, |target_tag| mineral_tags.contains(&target_tag))
 					}));
 					
-					for _ in 0..(ideal_harvesters - assigned_harvesters) {
+					for _ in 0..(target_gas_workers - assigned_harvesters) {
 						deficit_geysers.push(gas.clone());
 					}
 				}
 				Ordering::Greater => self.add_excess_workers_from_gas(&bases, gas, &mut idle_workers, target_gas_workers),
 				_ => {}
 			}
-		});
+		}
 
 		// Distributing idle workers
 		let minerals_near_base = if idle_workers.len() > deficit_minings.len() + deficit_geysers.len() {
@@ -290,13 +419,14 @@ impl FlourishBot {
 		}
 	}
 
-	fn get_builder(&self, pos: Point2, mineral_tags: &[u64]) -> Option<&Unit> {
+	pub fn get_builder(&self, pos: Point2, mineral_tags: &[u64]) -> Option<&Unit> {
 		self.units
 			.my
 			.workers
 			.iter()
 			.filter(|u| {
 				!(u.is_constructing()
+					|| self.is_worker_reserved(u.tag())
 					|| u.is_returning() || u.is_carrying_resource()
 					|| (u.is_gathering() && u.target_tag().map_or(true, |tag| !mineral_tags.contains(&tag))))
 			})
@@ -308,11 +438,16 @@ impl FlourishBot {
 			return;
 		}
 
-		let tech_buildings = vec![
+		let mut tech_buildings = vec![
 			(UnitTypeId::SpawningPool, 1, 0.0),
 			(UnitTypeId::EvolutionChamber, 2, 0.0),
 			(UnitTypeId::RoachWarren, 1, 280.0)
 		];
+		if let Some(tech) = self.anti_air_tech() {
+			if self.has_prereq(UnitTypeId::Lair) || self.has_prereq(UnitTypeId::Hive) {
+				tech_buildings.push((tech, 1, 0.0));
+			}
+		}
 
 		let mineral_tags = self
 			.units
@@ -328,7 +463,10 @@ impl FlourishBot {
 
 			if self.can_afford(tech_building, false) {
 				let place = self.start_location.towards(self.game_info.map_center, 6.0);
-				if let Some(location) = self.find_placement(tech_building, place, Default::default()) {
+				let location = self
+					.find_tech_placement(tech_building)
+					.or_else(|| self.find_placement(tech_building, place, Default::default()));
+				if let Some(location) = location {
 					if let Some(builder) = self.get_builder(location, &mineral_tags) {
 						builder.build(tech_building, location, false);
 						self.subtract_resources(tech_building, false);
@@ -343,13 +481,21 @@ impl FlourishBot {
 
 		let extractor = UnitTypeId::Extractor;
 		let hatchery = UnitTypeId::Hatchery;		
-		let num_extractors = self.counter().all().count(extractor);
+		let num_depleted_extractors = self
+			.units
+			.my
+			.gas_buildings
+			.iter()
+			.of_type(extractor)
+			.ready()
+			.filter(|g| g.vespene_contents() == Some(0))
+			.count();
+		let num_extractors = self.counter().all().count(extractor).saturating_sub(num_depleted_extractors);
 		let num_hatcheries = self.counter().all().count(hatchery);
 
 		let has_extractors_for_hatcheries = num_extractors >= 2 * num_hatcheries;
 		if !self.has_enough_gas && !has_extractors_for_hatcheries && self.can_afford(extractor, false) {
-			let start = self.start_location;
-			if let Some(geyser) = self.find_gas_placement(start) {
+			if let Some(geyser) = self.find_gas_placement_at_bases() {
 				if let Some(builder) = self.get_builder(geyser.position(), &mineral_tags) {
 					builder.build_gas(geyser.tag(), false);
 					self.subtract_resources(extractor, false);
@@ -357,10 +503,18 @@ impl FlourishBot {
 			}
 		}
 
-		if self.can_afford(hatchery, false) && num_hatcheries < 1 + (self.time / 160.0) as usize {
-			if let Some(exp) = self.get_expansion() {
-				if let Some(builder) = self.get_builder(exp.loc, &mineral_tags) {
-					builder.build(hatchery, exp.loc, false);
+		if self.can_afford(hatchery, false) {
+			let location = if self.should_expand() {
+				self.choose_expansion()
+			} else if self.wants_macro_hatch() {
+				self.find_macro_hatch_placement()
+			} else {
+				None
+			};
+
+			if let Some(location) = location {
+				if let Some(builder) = self.get_builder(location, &mineral_tags) {
+					builder.build(hatchery, location, false);
 					self.subtract_resources(hatchery, false);
 				}
 			}
@@ -370,7 +524,9 @@ impl FlourishBot {
 		let hive = UnitTypeId::Hive;
 		let num_lairs = self.counter().all().count(lair);
 		let num_hives = self.counter().all().count(hive);
-		if self.can_afford_multiple(lair, false, 2) && num_lairs + num_hives == 0 && self.time > 7.0 * 60.0 {
+		// Overseers and our anti-air both need lair tech, so get it early if we need them.
+		let needs_lair_tech = self.wants_anti_air() || self.surveillance.has_cloak_threat();
+		if self.can_afford_multiple(lair, false, 2) && num_lairs + num_hives == 0 && (self.time > 7.0 * 60.0 || needs_lair_tech) {
 			if let Some(hatchery) = self.units.my.townhalls.iter().of_type(hatchery).closest(self.start_location) {
 				hatchery.train(lair, false);
 			}
@@ -383,20 +539,15 @@ impl FlourishBot {
 			return;
 		}
 
-		// Order one queen per each base
-		let queen = UnitTypeId::Queen;
-		if self.counter().all().count(queen) < self.units.my.townhalls.len() && self.can_afford(queen, true) {
-			if let Some(townhall) = self.units.my.townhalls.first() {
-				townhall.train(queen, false);
-				self.subtract_resources(queen, true);
-			}
-		}
+		self.train_queens();
 
 		// Can't order units without larva
 		if self.units.my.larvas.is_empty() {
 			return;
 		}
 
+		self.train_anti_air();
+
 		let over = UnitTypeId::Overlord;
 		let mut overs_under_prod = self.counter().ordered().count(over) as u32;
 
@@ -447,79 +598,11 @@ impl FlourishBot {
 	}
 
 	fn execute_micro(&mut self) {
-		// Injecting Larva
-		let mut queens = self.units.my.units.filter(|u| {
-			u.type_id() == UnitTypeId::Queen
-				&& !u.is_using(AbilityId::EffectInjectLarva)
-				&& u.has_ability(AbilityId::EffectInjectLarva)
-		});
-		if !queens.is_empty() {
-			self.units
-				.my
-				.townhalls
-				.iter()
-				.filter(|h| {
-					!h.has_buff(BuffId::QueenSpawnLarvaTimer)
-						|| h.buff_duration_remain().unwrap() * 20 > h.buff_duration_max().unwrap()
-				})
-				.for_each(|h| {
-					if let Some(queen) = queens.closest(h) {
-						queen.command(AbilityId::EffectInjectLarva, Target::Tag(h.tag()), false);
-						let tag = queen.tag();
-						queens.remove(tag);
-					}
-				});
-		}
+		self.update_creep_targets();
+		self.queen_micro();
+		self.spread_creep();
+		self.overseer_micro();
 
-		let zergling = UnitTypeId::Zergling;
-		let zerglings = self.units.my.units.of_type(zergling);
-		if zerglings.is_empty() {
-			return;
-		}
-
-		// Check if speed upgrade is >80% ready
-		let upgrades = vec![UpgradeId::Zerglingmovementspeed, UpgradeId::ZergMeleeWeaponsLevel1];
-		let upgrades_almost_ready = upgrades.iter().all(|upgrade| self.has_upgrade(*upgrade) || self.upgrade_progress(*upgrade) >= 0.8);
-		let num_zerglings: usize = self.counter().count(zergling);
-		let start_attack_threshold = 20;
-		let end_attack_threshold = 3;
-		let should_attack = upgrades_almost_ready && num_zerglings > start_attack_threshold || self.attacking && num_zerglings > end_attack_threshold;
-		self.attacking = should_attack;
-
-		// Attacking with zerglings or defending our locations
-		let targets = if should_attack {
-			self.units.enemy.all.ground()
-		} else {
-			self.units
-				.enemy
-				.all
-				.filter(|e| !e.is_flying() && self.units.my.townhalls.iter().any(|h| h.is_closer(25.0, *e)))
-		};
-		if !targets.is_empty() {
-			for u in &zerglings {
-				if let Some(target) = targets
-					.iter()
-					.in_range_of(u, 0.0)
-					.min_by_key(|t| t.hits())
-					.or_else(|| targets.closest(u))
-				{
-					// Don't get stuck trying to kill changelings
-					if target.type_id() == UnitTypeId::ChangelingZergling {
-						u.attack(Target::Tag(target.tag()), false);
-					} else {
-						u.attack(Target::Pos(target.position()), false);
-					}
-				}
-			}
-		} else {
-			let target = if should_attack {
-				self.enemy_start
-			} else {
-				self.start_location.towards(self.start_center, -8.0)
-			};
-			for u in &zerglings {
-				u.move_to(Target::Pos(target), false);
-			}
-		}
+		self.army_micro();
 	}
 }
\ No newline at end of file
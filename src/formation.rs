@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::f32::consts::PI;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+/// Angle of `point` as seen from `center`.
+fn angle_from(center: Point2, point: Point2) -> f32 {
+	(point.y - center.y).atan2(point.x - center.x)
+}
+
+/// Signed difference between two angles, in the range -PI to PI.
+fn angle_difference(a: f32, b: f32) -> f32 {
+	let mut difference = (a - b) % (2.0 * PI);
+	if difference > PI {
+		difference -= 2.0 * PI;
+	} else if difference < -PI {
+		difference += 2.0 * PI;
+	}
+	difference
+}
+
+fn point_at(center: Point2, angle: f32, radius: f32) -> Point2 {
+	Point2::new(center.x + radius * angle.cos(), center.y + radius * angle.sin())
+}
+
+/// Sorts units by their angle around `center`, measured relative to `facing` so the sort doesn't
+/// wrap around in the middle of a group.
+fn sort_by_angle(units: &mut Vec<&Unit>, center: Point2, facing: f32) {
+	units.sort_by(|a, b| {
+		let a = angle_difference(angle_from(center, a.position()), facing);
+		let b = angle_difference(angle_from(center, b.position()), facing);
+		a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+	});
+}
+
+// Doing this in FlourishBot so formations can check pathing and see the enemy's units.
+impl FlourishBot {
+	/// Squads with fewer melee units than this just run in.
+	const MIN_FORMATION_UNITS: usize = 6;
+	/// Enemies this close to our melee units are the ones we form up against.
+	const FORMATION_RANGE: f32 = 14.0;
+	/// How far outside the enemy's range the concave forms.
+	const CONCAVE_BUFFER: f32 = 2.0;
+	/// Space each unit takes along the concave.
+	const CONCAVE_SPACING: f32 = 1.0;
+	/// The concave never wraps further round the enemy than this.
+	const MAX_CONCAVE_ANGLE: f32 = 2.0 * PI / 3.0;
+	/// Share of units that need to be in place before the concave moves in.
+	const CONCAVE_FORMED_SHARE: f32 = 0.8;
+	/// We try to surround when we have at least this many melee units per enemy.
+	const SURROUND_RATIO: usize = 3;
+	/// Enemies slower than this share of our slowest melee unit can't run from a surround.
+	const SURROUND_SPEED_SHARE: f32 = 0.8;
+	/// How close a unit needs to be to its spot to count as there.
+	const FORMATION_SLOT_RADIUS: f32 = 1.5;
+
+	/// Units that can only hit what they're touching, and want to arrive at a fight all at once.
+	fn is_formation_melee(unit: &Unit) -> bool {
+		!unit.is_flying() && unit.type_id() != UnitTypeId::Baneling && unit.can_attack_ground() && unit.ground_range() < 1.5
+	}
+
+	/// Whether an enemy has too little open ground around it to get away.
+	fn is_cornered(&self, enemy: &Unit) -> bool {
+		let open = (0..8)
+			.map(|i| point_at(enemy.position(), i as f32 * PI / 4.0, 3.0))
+			.filter(|p| self.is_pathable(*p))
+			.count();
+		open < 4
+	}
+
+	/// Sends units to their matching spots. Units already there are left alone.
+	fn move_to_slots(units: &[&Unit], slots: &[Point2]) {
+		for (unit, slot) in units.iter().zip(slots) {
+			if !unit.is_closer(Self::FORMATION_SLOT_RADIUS, *slot) {
+				unit.move_to(Target::Pos(*slot), false);
+			}
+		}
+	}
+
+	/// Spreads a squad's melee units into a concave facing the enemy before a fight, or around
+	/// enemies that are too slow or cornered to escape. Returns the tags of units that were given
+	/// formation orders and shouldn't attack yet, and whether the concave has formed. Once it has,
+	/// `formed` should be passed back in so the squad goes in rather than forming up again every
+	/// time a few units fall out of place. It stops counting as formed once no enemies are in
+	/// range, so the next fight gets a fresh concave.
+	pub fn melee_formation(&self, members: &Units, targets: &Units, formed: bool) -> (HashSet<u64>, bool) {
+		let mut melee = members.iter().filter(|u| Self::is_formation_melee(u)).collect::<Vec<&Unit>>();
+		if melee.len() < Self::MIN_FORMATION_UNITS {
+			return (HashSet::new(), false);
+		}
+		let melee_center = match members.filter(|u| Self::is_formation_melee(u)).center() {
+			Some(center) => center,
+			None => return (HashSet::new(), false),
+		};
+
+		let enemies = targets.filter(|e| !e.is_structure() && !e.is_flying() && e.is_closer(Self::FORMATION_RANGE, melee_center));
+		let enemy_center = match enemies.center() {
+			Some(center) => center,
+			None => return (HashSet::new(), false),
+		};
+		if formed {
+			return (HashSet::new(), true);
+		}
+		let enemy_spread = enemies.iter().map(|e| e.distance(enemy_center) + e.radius()).fold(0.0, f32::max);
+		let facing = angle_from(enemy_center, melee_center);
+		sort_by_angle(&mut melee, enemy_center, facing);
+
+		let slowest = melee.iter().map(|u| u.speed()).fold(f32::INFINITY, f32::min);
+		let can_surround = enemies.len() * Self::SURROUND_RATIO <= melee.len()
+			&& enemies
+				.iter()
+				.all(|e| e.speed() < slowest * Self::SURROUND_SPEED_SHARE || self.is_cornered(e));
+
+		if can_surround {
+			// Spots all the way round, starting from the far side so the first units to arrive cut
+			// off the escape.
+			let radius = enemy_spread + 1.0;
+			let step = 2.0 * PI / melee.len() as f32;
+			let mut slots = (0..melee.len())
+				.map(|i| point_at(enemy_center, facing + PI + (i as f32 - melee.len() as f32 / 2.0) * step, radius))
+				.collect::<Vec<Point2>>();
+			slots.sort_by(|a, b| {
+				let a = angle_difference(angle_from(enemy_center, *a), facing);
+				let b = angle_difference(angle_from(enemy_center, *b), facing);
+				a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+			});
+
+			// Units that have reached their spot, or are already hitting something, get on with it.
+			let moving = melee
+				.iter()
+				.zip(&slots)
+				.filter(|(u, slot)| !u.is_closer(Self::FORMATION_SLOT_RADIUS, **slot) && !enemies.iter().any(|e| u.in_range(e, 0.0)))
+				.map(|(u, slot)| (*u, *slot))
+				.collect::<Vec<(&Unit, Point2)>>();
+			for (unit, slot) in &moving {
+				unit.move_to(Target::Pos(*slot), false);
+			}
+			return (moving.iter().map(|(u, _)| u.tag()).collect(), false);
+		}
+
+		// Once anyone is in a fight it's too late to form up.
+		let in_contact = melee.iter().any(|u| {
+			enemies
+				.iter()
+				.any(|e| e.distance(*u) < e.ground_range().max(1.0) + e.radius() + u.radius() + 1.0)
+		});
+		if in_contact {
+			return (HashSet::new(), true);
+		}
+
+		let enemy_range = enemies.iter().map(|e| e.ground_range()).fold(0.0, f32::max);
+		let radius = enemy_spread + enemy_range + Self::CONCAVE_BUFFER;
+		let arc = (melee.len() as f32 * Self::CONCAVE_SPACING / radius).min(Self::MAX_CONCAVE_ANGLE);
+		let step = if melee.len() > 1 { arc / (melee.len() - 1) as f32 } else { 0.0 };
+		let slots = (0..melee.len())
+			.map(|i| point_at(enemy_center, facing - arc / 2.0 + i as f32 * step, radius))
+			.map(|p| if self.is_pathable(p) { p } else { enemy_center.towards(melee_center, radius) })
+			.collect::<Vec<Point2>>();
+
+		let in_place = melee.iter().zip(&slots).filter(|(u, slot)| u.is_closer(Self::FORMATION_SLOT_RADIUS, **slot)).count();
+		if in_place as f32 >= melee.len() as f32 * Self::CONCAVE_FORMED_SHARE {
+			return (HashSet::new(), true);
+		}
+
+		Self::move_to_slots(&melee, &slots);
+		(melee.iter().map(|u| u.tag()).collect(), false)
+	}
+}
@@ -0,0 +1,201 @@
+use rust_sc2::prelude::*;
+
+use crate::{
+	combat_sim::CombatDecision,
+	flourish_bot::FlourishBot,
+	squads::{SquadRole, SquadState},
+};
+
+/// Distance from `point` to the line segment between `from` and `to`.
+fn distance_to_segment(point: Point2, from: Point2, to: Point2) -> f32 {
+	let (dx, dy) = (to.x - from.x, to.y - from.y);
+	let length_squared = dx * dx + dy * dy;
+	if length_squared == 0.0 {
+		return point.distance(from);
+	}
+	let t = (((point.x - from.x) * dx + (point.y - from.y) * dy) / length_squared).clamp(0.0, 1.0);
+	point.distance(Point2::new(from.x + t * dx, from.y + t * dy))
+}
+
+// Doing this in FlourishBot so we can have mutable access to the harass squad while viewing
+// units.
+impl FlourishBot {
+	const HARASS_SIZE: usize = 8;
+	/// A harass squad smaller than this can't do enough damage to be worth keeping out.
+	const HARASS_MIN_SIZE: usize = 3;
+	/// Bases with more defense than this, in supply, aren't worth running into.
+	const HARASS_MAX_DEFENSE: f32 = 6.0;
+	/// Seconds between the end of one harassment run and the start of the next.
+	const HARASS_COOLDOWN: f32 = 60.0;
+	/// How wide a berth harassment gives the enemy army on the way in and out.
+	const ENEMY_ARMY_AVOID_RADIUS: f32 = 20.0;
+	/// Enemies this close to the harass squad are the ones it deals with.
+	const HARASS_SIGHT: f32 = 12.0;
+
+	/// The weakest defended enemy base with a townhall that isn't where the main army is
+	/// headed, so the two split the enemy's attention.
+	fn harass_base(&self, main_target: Option<Point2>) -> Option<Point2> {
+		self.known_enemy_bases()
+			.into_iter()
+			.filter(|b| b.has_townhall && b.defense <= Self::HARASS_MAX_DEFENSE)
+			.filter(|b| main_target.map_or(true, |t| !b.position.is_closer(Self::ENEMY_ARMY_AVOID_RADIUS, t)))
+			.min_by(|a, b| a.defense.partial_cmp(&b.defense).unwrap_or(std::cmp::Ordering::Equal))
+			.map(|b| b.position)
+	}
+
+	/// Where the enemy army was last seen, as the center of every enemy army unit we remember.
+	fn enemy_army_position(&self) -> Option<Point2> {
+		let positions = self.surveillance.remembered_enemy_units().map(|u| u.position()).collect::<Vec<Point2>>();
+		if positions.is_empty() {
+			return None;
+		}
+		let count = positions.len() as f32;
+		let (x, y) = positions.iter().fold((0.0, 0.0), |(x, y), p| (x + p.x, y + p.y));
+		Some(Point2::new(x / count, y / count))
+	}
+
+	/// A point to go through on the way from `from` to `to` that keeps clear of the enemy army, or
+	/// None if the straight path already does.
+	fn harass_waypoint(&self, from: Point2, to: Point2) -> Option<Point2> {
+		let army = self.enemy_army_position()?;
+		if distance_to_segment(army, from, to) >= Self::ENEMY_ARMY_AVOID_RADIUS {
+			return None;
+		}
+
+		let length = from.distance(to).max(1.0);
+		let (px, py) = (-(to.y - from.y) / length, (to.x - from.x) / length);
+		// Go round on whichever side of the path the army isn't.
+		let side = if (army.x - from.x) * px + (army.y - from.y) * py > 0.0 { -1.0 } else { 1.0 };
+
+		[side, -side]
+			.iter()
+			.map(|s| Point2::new(army.x + s * px * Self::ENEMY_ARMY_AVOID_RADIUS, army.y + s * py * Self::ENEMY_ARMY_AVOID_RADIUS))
+			.find(|p| self.is_pathable(*p))
+	}
+
+	/// Enemies the harass squad should kill: workers if there are any nearby, otherwise whatever
+	/// units are in the way.
+	pub fn harass_targets(&self, members: &Units) -> Units {
+		let center = match members.center() {
+			Some(center) => center,
+			None => return Units::new(),
+		};
+
+		let workers = self.units.enemy.workers.filter(|w| w.is_closer(Self::HARASS_SIGHT, center));
+		if !workers.is_empty() {
+			return workers;
+		}
+		self.units
+			.enemy
+			.units
+			.filter(|e| !e.is_flying() && e.is_closer(Self::HARASS_SIGHT, center))
+	}
+
+	/// Splits fast zerglings off from the main army while it attacks, and sends them at another
+	/// base.
+	fn start_harass(&mut self) {
+		if !self.army.is_attacking()
+			|| !self.has_upgrade(UpgradeId::Zerglingmovementspeed)
+			|| self.time < self.army.last_harass + Self::HARASS_COOLDOWN
+		{
+			return;
+		}
+
+		let army = self.army_units();
+		let main_target = self.army.squad(SquadRole::MainArmy).and_then(|s| s.target);
+		let mut lings = match self.army.squad(SquadRole::MainArmy) {
+			Some(main) => main.units(&army).of_type(UnitTypeId::Zergling),
+			None => return,
+		};
+		if lings.len() < 2 * Self::HARASS_SIZE {
+			return;
+		}
+		let base = match self.harass_base(main_target) {
+			Some(base) => base,
+			None => return,
+		};
+
+		// Take the lings furthest back, which are the least caught up in the main fight.
+		let start = self.start_location;
+		lings.sort(|u| u.distance_squared(start) as u32);
+		let chosen = lings.iter().take(Self::HARASS_SIZE).map(|u| u.tag()).collect::<Vec<u64>>();
+		let from = lings.center().unwrap_or(start);
+		let waypoint = self.harass_waypoint(from, base);
+
+		for tag in chosen {
+			self.army.assign(tag, SquadRole::Harass);
+		}
+		if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+			harass.state = SquadState::Attacking;
+			harass.target = Some(base);
+			harass.waypoint = waypoint;
+		}
+	}
+
+	/// Runs the harass squad: heads for its base round the enemy army, runs home once defense
+	/// shows up, and rejoins the army once it's back.
+	pub fn update_harass(&mut self) {
+		let army = self.army_units();
+		let (state, members, waypoint, target) = match self.army.squad(SquadRole::Harass) {
+			Some(harass) => (harass.state, harass.units(&army), harass.waypoint, harass.target),
+			None => return,
+		};
+		let center = match members.center() {
+			Some(center) => center,
+			None => {
+				if state != SquadState::Idle {
+					self.army.last_harass = self.time;
+				}
+				if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+					harass.state = SquadState::Idle;
+					harass.target = None;
+					harass.waypoint = None;
+				}
+				self.start_harass();
+				return;
+			}
+		};
+
+		let reached_waypoint = waypoint.map_or(false, |w| center.is_closer(Self::REGROUP_RADIUS, w));
+		let reached_target = target.map_or(true, |t| center.is_closer(Self::REGROUP_RADIUS, t));
+
+		match state {
+			SquadState::Attacking => {
+				let defended = self
+					.army
+					.squad(SquadRole::Harass)
+					.and_then(|s| self.local_fight(s, &army))
+					.map_or(false, |d| d != CombatDecision::Engage);
+
+				if members.len() < Self::HARASS_MIN_SIZE || defended || self.has_undetected_threat_near(center) {
+					let home = self.regroup_point(center);
+					let waypoint = self.harass_waypoint(center, home);
+					if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+						harass.state = SquadState::Retreating;
+						harass.target = Some(home);
+						harass.waypoint = waypoint;
+					}
+				} else if reached_waypoint {
+					if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+						harass.waypoint = None;
+					}
+				}
+			}
+			SquadState::Retreating if waypoint.is_none() && reached_target => {
+				let rejoin = if self.army.is_attacking() { SquadRole::Reinforcement } else { SquadRole::MainArmy };
+				self.army.merge(SquadRole::Harass, rejoin);
+				self.army.last_harass = self.time;
+				if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+					harass.state = SquadState::Idle;
+					harass.target = None;
+				}
+			}
+			SquadState::Retreating if reached_waypoint => {
+				if let Some(harass) = self.army.squad_mut(SquadRole::Harass) {
+					harass.waypoint = None;
+				}
+			}
+			_ => {}
+		}
+	}
+}
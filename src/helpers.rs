@@ -4,7 +4,7 @@ use crate::flourish_bot::FlourishBot;
 
 impl FlourishBot {
 	/// Tags of the mineral fields close enough to base to be "base's" mineral fields.
-	fn local_mineral_tags(&self, base: &Unit) -> Vec<u64> {
+	pub fn local_mineral_tags(&self, base: &Unit) -> Vec<u64> {
 		self.units.mineral_fields
 			.iter()
 			.closer(11.0, base)
@@ -12,6 +12,11 @@ impl FlourishBot {
 			.collect::<Vec<u64>>()
 	}
 
+	/// Whether a worker has been taken off mining by another system and shouldn't be redistributed.
+	pub fn is_worker_reserved(&self, tag: u64) -> bool {
+		self.worker_defense.is_defending(tag) || self.evacuation.is_evacuated(tag)
+	}
+
 	/// Adds all workers at the base that don't need to be there to the idle_workers collection.
 	pub fn add_excess_workers_from_base(&self, base: &Unit, idle_workers: &mut Units) {
 		let local_minerals = self.local_mineral_tags(base);
@@ -25,7 +30,7 @@ impl FlourishBot {
 				.workers
 				.iter()
 				.filter(|u| {
-					u.target_tag().map_or(false, |target_tag| {
+					!self.is_worker_reserved(u.tag()) && u.target_tag().map_or(false, |target_tag| {
 						local_minerals.contains(&target_tag)
 							|| (u.is_carrying_minerals() && target_tag == base.tag())
 					})
@@ -47,7 +52,7 @@ impl FlourishBot {
 				.workers
 				.iter()
 				.filter(|u| {
-					u.target_tag().map_or(false, |target_tag| {
+					!self.is_worker_reserved(u.tag()) && u.target_tag().map_or(false, |target_tag| {
 						target_tag == gas.tag()
 							|| (u.is_carrying_vespene()
 								&& target_tag == bases.closest(gas).unwrap().tag())
@@ -58,6 +63,38 @@ impl FlourishBot {
 		);
 	}
 
+	/// Whether there are enemy army units close enough to a base to be attacking it.
+	pub fn is_base_threatened(&self, base: &Unit) -> bool {
+		self.units
+			.enemy
+			.units
+			.iter()
+			.any(|e| !e.is_worker() && e.can_attack() && e.is_closer(15.0, base))
+	}
+
+	/// Finds a free geyser with gas left in it next to one of our completed townhalls, preferring
+	/// bases that aren't under attack, then bases closer to our main.
+	pub fn find_gas_placement_at_bases(&self) -> Option<Unit> {
+		let mut bases = self.units.my.townhalls.ready();
+		bases.sort(|b| (self.is_base_threatened(b), b.distance_squared(self.start_location) as u32));
+
+		let taken = |geyser: &Unit| {
+			self.units.my.gas_buildings.iter().any(|g| g.is_closer(1.0, geyser))
+				|| self.units.enemy.gas_buildings.iter().any(|g| g.is_closer(1.0, geyser))
+				|| self.units.my.workers.iter().any(|w| w.target_tag() == Some(geyser.tag()))
+		};
+
+		bases.iter().find_map(|base| {
+			self.units
+				.vespene_geysers
+				.closer(11.0, base)
+				.iter()
+				.filter(|g| g.vespene_contents().map_or(false, |v| v > 0) && !taken(*g))
+				.closest(base)
+				.cloned()
+		})
+	}
+
 	/// Checks if bot has enough resources and supply to build given unit type, if we want a buffer
 	/// left over at the end.
 	pub fn can_afford_with_buffer(&self, unit: UnitTypeId, check_supply: bool, buffer_minerals: u32, buffer_vespene: u32) -> bool {
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use rust_sc2::{prelude::*, consts::FRAMES_PER_SECOND};
+
+use crate::flourish_bot::FlourishBot;
+
+#[derive(Default)]
+pub struct InjectTracker {
+	/// Seconds each townhall has spent without an inject running, keyed by townhall tag.
+	missed: HashMap<u64, f32>,
+	last_time: f32,
+}
+
+impl InjectTracker {
+	/// Seconds the given townhall has gone without an inject this game.
+	pub fn missed_inject_time(&self, townhall: u64) -> f32 {
+		self.missed.get(&townhall).copied().unwrap_or(0.0)
+	}
+
+	/// Seconds all our townhalls together have gone without injects this game.
+	pub fn total_missed_inject_time(&self) -> f32 {
+		self.missed.values().sum()
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to InjectTracker while viewing
+// units.
+impl FlourishBot {
+	const INJECT_ENERGY: u32 = 25;
+
+	/// Seconds left on the inject currently running on a townhall, or 0 if there isn't one.
+	fn inject_time_remaining(townhall: &Unit) -> f32 {
+		if townhall.has_buff(BuffId::QueenSpawnLarvaTimer) {
+			townhall.buff_duration_remain().unwrap_or(0) as f32 / FRAMES_PER_SECOND
+		} else {
+			0.0
+		}
+	}
+
+	/// Sends a queen to inject a townhall so that she arrives just as its current inject runs out.
+	/// Queens without the energy for it are left alone.
+	pub fn inject_with_queen(&self, queen: &Unit, townhall: &Unit) {
+		if queen.is_using(AbilityId::EffectInjectLarva)
+			|| queen.energy().map_or(true, |e| e < Self::INJECT_ENERGY)
+			|| !queen.has_ability(AbilityId::EffectInjectLarva)
+		{
+			return;
+		}
+
+		let distance = (queen.distance(townhall) - queen.radius() - townhall.radius()).max(0.0);
+		let travel_time = if queen.speed() > 0.0 { distance / queen.speed() } else { 0.0 };
+
+		if Self::inject_time_remaining(townhall) <= travel_time {
+			queen.command(AbilityId::EffectInjectLarva, Target::Tag(townhall.tag()), false);
+		}
+	}
+
+	/// Adds the time since the last step to every completed townhall that has no inject running.
+	pub fn track_missed_injects(&mut self) {
+		let elapsed = (self.time - self.inject_tracker.last_time).max(0.0);
+		self.inject_tracker.last_time = self.time;
+
+		// Nothing to inject with until the first queen is out.
+		if self.counter().count(UnitTypeId::Queen) == 0 {
+			return;
+		}
+
+		for townhall in self.units.my.townhalls.ready().iter() {
+			if !townhall.has_buff(BuffId::QueenSpawnLarvaTimer) {
+				*self.inject_tracker.missed.entry(townhall.tag()).or_insert(0.0) += elapsed;
+			}
+		}
+	}
+
+	pub fn log_missed_injects(&self) {
+		let per_townhall = self
+			.units
+			.my
+			.townhalls
+			.iter()
+			.map(|t| format!("{}: {:.0}s", t.tag(), self.inject_tracker.missed_inject_time(t.tag())))
+			.collect::<Vec<String>>()
+			.join(", ");
+		println!(
+			"[{:>5.0}s] missed inject time {:.0}s ({})",
+			self.time,
+			self.inject_tracker.total_missed_inject_time(),
+			per_townhall,
+		);
+	}
+}
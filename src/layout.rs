@@ -0,0 +1,394 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+use crate::terrain::{tile_center, Grid, Tile};
+
+/// Reserved building spots around a single base.
+pub struct BaseLayout {
+	pub base: Point2,
+	/// Centres of 3x3 spots for tech structures, best first.
+	pub tech: Vec<Point2>,
+	/// Centres of 2x2 spots for spine crawlers, facing the way attacks come from.
+	pub spines: Vec<Point2>,
+	/// Centres of 2x2 spots for spore crawlers, in the mineral line.
+	pub spores: Vec<Point2>,
+	/// Centres of 5x5 spots for hatcheries built for their larva, behind the tech.
+	pub macro_hatches: Vec<Point2>,
+}
+
+/// What the layout planner needs to know about one base.
+pub struct BaseSite<'a> {
+	pub base: Point2,
+	/// Positions of the base's mineral fields and geysers.
+	pub resources: &'a [Point2],
+	/// Where attacks on this base come from; spines go between it and the base.
+	pub defend_towards: Point2,
+}
+
+pub struct LayoutPlanner<'a> {
+	placement: &'a Grid,
+	pathing: &'a Grid,
+	/// Tiles that must stay walkable, like ramps.
+	keep_clear: HashSet<Tile>,
+	/// Tiles already promised to a building, so layouts of neighbouring bases don't overlap.
+	reserved: HashSet<Tile>,
+}
+
+impl<'a> LayoutPlanner<'a> {
+	const TECH_SPOTS: usize = 4;
+	const SPINE_SPOTS: usize = 4;
+	const SPORE_SPOTS: usize = 2;
+	const MACRO_HATCH_SPOTS: usize = 2;
+	/// How far from the base centre we look for spots.
+	const SEARCH_RADIUS: isize = 14;
+	/// Half the width of the walking lanes kept clear between a base and its resources and its
+	/// defensive position.
+	const LANE_HALF_WIDTH: f32 = 1.5;
+	/// Clear space kept around ramps and other tiles that must stay walkable.
+	const KEEP_CLEAR_MARGIN: isize = 2;
+
+	pub fn new(placement: &'a Grid, pathing: &'a Grid, keep_clear: &[Tile]) -> Self {
+		let mut margin = HashSet::new();
+		for &(x, y) in keep_clear {
+			for dy in -Self::KEEP_CLEAR_MARGIN..=Self::KEEP_CLEAR_MARGIN {
+				for dx in -Self::KEEP_CLEAR_MARGIN..=Self::KEEP_CLEAR_MARGIN {
+					let (nx, ny) = (x as isize + dx, y as isize + dy);
+					if nx >= 0 && ny >= 0 {
+						margin.insert((nx as usize, ny as usize));
+					}
+				}
+			}
+		}
+
+		Self {
+			placement,
+			pathing,
+			keep_clear: margin,
+			reserved: HashSet::new(),
+		}
+	}
+
+	/// Reserves spots for tech, spines and spores around a base. Results only depend on the grids
+	/// and the bases planned before this one, so the same inputs always give the same layout.
+	pub fn plan(&mut self, site: &BaseSite) -> BaseLayout {
+		// Nothing goes on top of the townhall itself.
+		let townhall = Self::footprint(site.base, 5);
+		self.reserved.extend(townhall.iter().copied());
+
+		let resource_center = if site.resources.is_empty() {
+			site.base
+		} else {
+			site.resources.iter().fold(Point2::new(0.0, 0.0), |acc, r| acc + *r) / site.resources.len() as f32
+		};
+
+		let mut mining_lanes = HashSet::new();
+		for resource in site.resources {
+			self.add_lane(&mut mining_lanes, site.base, *resource);
+		}
+		let mut defense_lane = HashSet::new();
+		self.add_lane(&mut defense_lane, site.base, site.defend_towards);
+
+		// Spores sit among the drones they protect, so they're allowed in the mining lanes.
+		let spore_anchor = site.base.towards(resource_center, 3.5);
+		let spores = self.take_spots(site.base, spore_anchor, 2, Self::SPORE_SPOTS, &defense_lane);
+
+		let mut blocked = mining_lanes.clone();
+		blocked.extend(defense_lane.iter().copied());
+
+		// Spines stand either side of the lane to the defensive position rather than in it.
+		let spines = self.take_spots(site.base, site.defend_towards, 2, Self::SPINE_SPOTS, &blocked);
+
+		// Tech goes behind the base, away from both the minerals and the way attacks come from.
+		let away = (site.base - resource_center) + (site.base - site.defend_towards);
+		let tech_anchor = if away.x == 0.0 && away.y == 0.0 {
+			site.base
+		} else {
+			site.base.towards(site.base + away, 6.0)
+		};
+		let tech = self.take_spots(site.base, tech_anchor, 3, Self::TECH_SPOTS, &blocked);
+		let macro_hatches = self.take_spots(site.base, tech_anchor, 5, Self::MACRO_HATCH_SPOTS, &blocked);
+
+		BaseLayout {
+			base: site.base,
+			tech,
+			spines,
+			spores,
+			macro_hatches,
+		}
+	}
+
+	/// Marks every tile within LANE_HALF_WIDTH of the line between two points.
+	fn add_lane(&self, lane: &mut HashSet<Tile>, from: Point2, to: Point2) {
+		let length = from.distance(to);
+		let mut travelled = 0.0;
+		while travelled <= length {
+			let point = from.towards(to, travelled);
+			for tile in Self::footprint(point, (2.0 * Self::LANE_HALF_WIDTH).ceil() as usize + 1) {
+				if tile_center(tile).distance(point) <= Self::LANE_HALF_WIDTH {
+					lane.insert(tile);
+				}
+			}
+			travelled += 0.5;
+		}
+	}
+
+	/// Tiles covered by a square building of the given size centred on a point.
+	fn footprint(center: Point2, size: usize) -> Vec<Tile> {
+		let half = size as f32 / 2.0;
+		let (x0, y0) = ((center.x - half).round() as isize, (center.y - half).round() as isize);
+		(0..size as isize)
+			.flat_map(|dy| (0..size as isize).map(move |dx| (x0 + dx, y0 + dy)))
+			.filter(|&(x, y)| x >= 0 && y >= 0)
+			.map(|(x, y)| (x as usize, y as usize))
+			.collect()
+	}
+
+	/// Where a building of the given size would be centred if its bottom-left tile were (x, y).
+	fn center_for(x: isize, y: isize, size: usize) -> Point2 {
+		let half = size as f32 / 2.0;
+		Point2::new(x as f32 + half, y as f32 + half)
+	}
+
+	fn fits(&self, tiles: &[Tile], blocked: &HashSet<Tile>) -> bool {
+		tiles.iter().all(|t| {
+			self.placement.get(*t)
+				&& self.pathing.get(*t)
+				&& !self.reserved.contains(t)
+				&& !self.keep_clear.contains(t)
+				&& !blocked.contains(t)
+		})
+	}
+
+	/// Reserves up to `count` non-overlapping spots of the given size, closest to the anchor first.
+	fn take_spots(&mut self, base: Point2, anchor: Point2, size: usize, count: usize, blocked: &HashSet<Tile>) -> Vec<Point2> {
+		let (bx, by) = (base.x as isize, base.y as isize);
+		let mut candidates = (-Self::SEARCH_RADIUS..=Self::SEARCH_RADIUS)
+			.flat_map(|dy| (-Self::SEARCH_RADIUS..=Self::SEARCH_RADIUS).map(move |dx| (bx + dx, by + dy)))
+			.filter(|&(x, y)| x >= 0 && y >= 0)
+			.map(|(x, y)| Self::center_for(x, y, size))
+			.collect::<Vec<Point2>>();
+		// Ties are broken by position so the order never depends on anything but the inputs.
+		candidates.sort_by(|a, b| {
+			a.distance_squared(anchor)
+				.partial_cmp(&b.distance_squared(anchor))
+				.unwrap_or(Ordering::Equal)
+				.then(a.y.partial_cmp(&b.y).unwrap_or(Ordering::Equal))
+				.then(a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal))
+		});
+
+		let mut spots = Vec::new();
+		for candidate in candidates {
+			if spots.len() >= count {
+				break;
+			}
+			let tiles = Self::footprint(candidate, size);
+			if tiles.len() == size * size && self.fits(&tiles, blocked) {
+				self.reserved.extend(tiles);
+				spots.push(candidate);
+			}
+		}
+		spots
+	}
+}
+
+impl FlourishBot {
+	/// Plans building layouts for every expansion location. Needs the terrain analysis.
+	pub fn plan_layouts(&mut self) {
+		let terrain = match &self.terrain {
+			Some(terrain) => terrain,
+			None => return,
+		};
+
+		let resources = self
+			.units
+			.mineral_fields
+			.iter()
+			.chain(self.units.vespene_geysers.iter())
+			.map(|r| r.position())
+			.collect::<Vec<Point2>>();
+
+		let mut bases = self.expansions.iter().map(|e| e.loc).collect::<Vec<Point2>>();
+		// Plan our main first so it gets first pick of any contested tiles.
+		let start = self.start_location;
+		bases.sort_by(|a, b| {
+			a.distance_squared(start)
+				.partial_cmp(&b.distance_squared(start))
+				.unwrap_or(Ordering::Equal)
+		});
+
+		let mut planner = LayoutPlanner::new(terrain.placement(), terrain.pathing(), terrain.main_ramp_tiles());
+		let layouts = bases
+			.iter()
+			.map(|base| {
+				let local_resources = resources
+					.iter()
+					.filter(|r| r.distance(*base) < 11.0)
+					.copied()
+					.collect::<Vec<Point2>>();
+				let defend_towards = terrain
+					.defensive_position(*base)
+					.unwrap_or_else(|| base.towards(self.game_info.map_center, 7.0));
+				planner.plan(&BaseSite {
+					base: *base,
+					resources: &local_resources,
+					defend_towards,
+				})
+			})
+			.collect();
+
+		self.layouts = layouts;
+	}
+
+	/// The planned layout of the base at the given location, if it has one.
+	pub fn layout_at(&self, base: Point2) -> Option<&BaseLayout> {
+		self.layouts.iter().find(|l| l.base.distance(base) < 1.0)
+	}
+
+	/// A free planned spot for a tech structure, in our main if possible and otherwise at the
+	/// closest completed base to it.
+	pub fn find_tech_placement(&self, building: UnitTypeId) -> Option<Point2> {
+		let mut bases = self.units.my.townhalls.ready();
+		bases.sort(|b| b.distance_squared(self.start_location) as u32);
+
+		bases
+			.iter()
+			.filter_map(|b| self.layout_at(b.position()))
+			.flat_map(|l| l.tech.iter())
+			.find(|spot| self.can_place(building, **spot))
+			.copied()
+	}
+
+	/// A free planned spot for a macro hatchery in our main.
+	pub fn find_macro_hatch_placement(&self) -> Option<Point2> {
+		self.layout_at(self.start_location)?
+			.macro_hatches
+			.iter()
+			.find(|spot| self.can_place(UnitTypeId::Hatchery, **spot))
+			.copied()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::VecDeque;
+
+	use super::*;
+
+	const SIZE: usize = 48;
+
+	fn open_grid() -> Grid {
+		Grid::from_fn(SIZE, SIZE, |_, _| true)
+	}
+
+	fn resources() -> Vec<Point2> {
+		(0..8).map(|i| Point2::new(13.0, 18.0 + i as f32)).collect()
+	}
+
+	fn ramp() -> Vec<Tile> {
+		(30..32).flat_map(|x| (20..24).map(move |y| (x, y))).collect()
+	}
+
+	fn site(resources: &[Point2]) -> BaseSite {
+		BaseSite {
+			base: Point2::new(20.5, 21.5),
+			resources,
+			defend_towards: Point2::new(34.5, 21.5),
+		}
+	}
+
+	fn all_footprints(layout: &BaseLayout) -> Vec<Vec<Tile>> {
+		let mut footprints = vec![LayoutPlanner::footprint(layout.base, 5)];
+		footprints.extend(layout.tech.iter().map(|s| LayoutPlanner::footprint(*s, 3)));
+		footprints.extend(layout.macro_hatches.iter().map(|s| LayoutPlanner::footprint(*s, 5)));
+		footprints.extend(layout.spines.iter().chain(&layout.spores).map(|s| LayoutPlanner::footprint(*s, 2)));
+		footprints
+	}
+
+	/// Tiles reachable from `from` on the grid once every footprint is built on.
+	fn reachable(grid: &Grid, built: &HashSet<Tile>, from: Tile) -> HashSet<Tile> {
+		let mut seen = HashSet::from([from]);
+		let mut queue = VecDeque::from([from]);
+		while let Some((x, y)) = queue.pop_front() {
+			let neighbours = [(x + 1, y), (x.wrapping_sub(1), y), (x, y + 1), (x, y.wrapping_sub(1))];
+			for n in neighbours {
+				if grid.get(n) && !built.contains(&n) && seen.insert(n) {
+					queue.push_back(n);
+				}
+			}
+		}
+		seen
+	}
+
+	#[test]
+	fn same_inputs_give_the_same_layout() {
+		let (placement, pathing) = (open_grid(), open_grid());
+		let resources = resources();
+
+		let first = LayoutPlanner::new(&placement, &pathing, &ramp()).plan(&site(&resources));
+		let second = LayoutPlanner::new(&placement, &pathing, &ramp()).plan(&site(&resources));
+
+		assert_eq!(first.tech, second.tech);
+		assert_eq!(first.spines, second.spines);
+		assert_eq!(first.spores, second.spores);
+		assert_eq!(first.macro_hatches, second.macro_hatches);
+		assert_eq!(first.tech.len(), LayoutPlanner::TECH_SPOTS);
+		assert_eq!(first.spines.len(), LayoutPlanner::SPINE_SPOTS);
+		assert_eq!(first.spores.len(), LayoutPlanner::SPORE_SPOTS);
+		assert_eq!(first.macro_hatches.len(), LayoutPlanner::MACRO_HATCH_SPOTS);
+	}
+
+	#[test]
+	fn spots_are_on_free_buildable_ground() {
+		// A hole in the ground next to the base that nothing may be placed on.
+		let hole = |x: usize, y: usize| (22..26).contains(&x) && (14..18).contains(&y);
+		let placement = Grid::from_fn(SIZE, SIZE, |x, y| !hole(x, y));
+		let pathing = Grid::from_fn(SIZE, SIZE, |x, y| !hole(x, y));
+		let resources = resources();
+		let layout = LayoutPlanner::new(&placement, &pathing, &ramp()).plan(&site(&resources));
+
+		let mut used = HashSet::new();
+		for tiles in all_footprints(&layout) {
+			for tile in tiles {
+				assert!(placement.get(tile) && pathing.get(tile), "{:?} isn't buildable", tile);
+				assert!(used.insert(tile), "{:?} is used twice", tile);
+			}
+		}
+	}
+
+	#[test]
+	fn mineral_line_and_ramp_stay_clear() {
+		let (placement, pathing) = (open_grid(), open_grid());
+		let resources = resources();
+		let planner = LayoutPlanner::new(&placement, &pathing, &ramp());
+		let mut mining_lanes = HashSet::new();
+		for resource in &resources {
+			planner.add_lane(&mut mining_lanes, site(&resources).base, *resource);
+		}
+		let keep_clear = planner.keep_clear.clone();
+
+		let mut planner = LayoutPlanner::new(&placement, &pathing, &ramp());
+		let layout = planner.plan(&site(&resources));
+		let footprints = all_footprints(&layout);
+
+		// Only spores may stand among the drones.
+		let in_lanes = layout.tech.iter().map(|s| LayoutPlanner::footprint(*s, 3))
+			.chain(layout.macro_hatches.iter().map(|s| LayoutPlanner::footprint(*s, 5)))
+			.chain(layout.spines.iter().map(|s| LayoutPlanner::footprint(*s, 2)))
+			.flatten()
+			.any(|t| mining_lanes.contains(&t));
+		assert!(!in_lanes);
+		assert!(!footprints.iter().flatten().any(|t| keep_clear.contains(t)));
+
+		// Every resource and the ramp can still be walked to from where attacks come from.
+		let built = footprints.into_iter().flatten().collect::<HashSet<Tile>>();
+		let defend_towards = site(&resources).defend_towards;
+		let walkable = reachable(&pathing, &built, (defend_towards.x as usize, defend_towards.y as usize));
+		for resource in &resources {
+			assert!(walkable.contains(&(resource.x as usize, resource.y as usize)), "{:?} is walled off", resource);
+		}
+		assert!(ramp().iter().all(|t| walkable.contains(t)));
+	}
+}
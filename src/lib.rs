@@ -1,6 +1,27 @@
+mod anti_air;
+mod attack_targets;
+mod base_hunt;
 mod helpers;
+mod injects;
+mod layout;
 mod prereqs;
+mod queens;
 mod build_scheduler;
+mod combat_sim;
+mod creep;
+mod detection;
+mod economy;
+mod evacuation;
+mod expansion;
+mod formation;
+mod harass;
+mod micro;
+mod squads;
+mod static_defense;
 mod surveillance;
+mod targeting;
+mod terrain;
+mod worker_transfer;
+mod worker_defense;
 
 pub mod flourish_bot;
\ No newline at end of file
@@ -0,0 +1,130 @@
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+// Per unit type behaviour that runs before a squad member falls back to attacking normally.
+impl FlourishBot {
+	/// Roaches burrow below this share of their health to regenerate.
+	const ROACH_BURROW_HEALTH: f32 = 0.35;
+	/// and come back up once they've healed to this share.
+	const ROACH_UNBURROW_HEALTH: f32 = 0.9;
+	const CORROSIVE_BILE_RANGE: f32 = 9.0;
+	/// Lurkers burrow once enemies get this close, and unburrow when none are within twice that.
+	const LURKER_BURROW_RANGE: f32 = 8.0;
+	/// How far ranged units step back when kiting.
+	const KITE_DISTANCE: f32 = 2.0;
+
+	/// Runs the special behaviour for a unit's type, if it has any that applies right now.
+	/// Returns whether the unit was given an order, in which case it shouldn't get another.
+	pub fn unit_micro(&self, unit: &Unit, targets: &Units) -> bool {
+		match unit.type_id() {
+			UnitTypeId::Roach | UnitTypeId::RoachBurrowed => self.roach_micro(unit, targets),
+			UnitTypeId::Ravager => self.ravager_micro(unit, targets) || self.kite(unit, targets),
+			UnitTypeId::LurkerMP | UnitTypeId::LurkerMPBurrowed => self.lurker_micro(unit, targets),
+			UnitTypeId::Hydralisk | UnitTypeId::Mutalisk => self.kite(unit, targets),
+			_ => false,
+		}
+	}
+
+	/// Burrows hurt roaches to regenerate, moving them out of the fight underground if they can.
+	fn roach_micro(&self, roach: &Unit, targets: &Units) -> bool {
+		if !self.has_upgrade(UpgradeId::Burrow) {
+			return self.kite(roach, targets);
+		}
+
+		let health = roach.health_percentage().unwrap_or(1.0);
+		if roach.is_burrowed() {
+			if health >= Self::ROACH_UNBURROW_HEALTH {
+				roach.use_ability(AbilityId::BurrowUpRoach, false);
+			} else if self.has_upgrade(UpgradeId::TunnelingClaws) {
+				if let Some(closest) = targets.closest(roach) {
+					roach.move_to(Target::Pos(roach.position().towards(closest.position(), -Self::KITE_DISTANCE)), false);
+				}
+			}
+			return true;
+		}
+
+		if health < Self::ROACH_BURROW_HEALTH {
+			roach.use_ability(AbilityId::BurrowDownRoach, false);
+			return true;
+		}
+
+		self.kite(roach, targets)
+	}
+
+	/// Throws corrosive bile at whatever can't easily dodge it: structures, sieged tanks and other
+	/// slow or immobile targets, then anything else in range.
+	fn ravager_micro(&self, ravager: &Unit, targets: &Units) -> bool {
+		if !ravager.has_ability(AbilityId::EffectCorrosiveBile) {
+			return false;
+		}
+
+		let in_range = targets
+			.iter()
+			.closer(Self::CORROSIVE_BILE_RANGE + ravager.radius(), ravager)
+			.filter(|t| !t.is_flying() || t.type_id() == UnitTypeId::LiberatorAG)
+			.collect::<Vec<&Unit>>();
+		let target = in_range
+			.iter()
+			.find(|t| t.is_structure() || t.speed() == 0.0 || t.type_id() == UnitTypeId::SiegeTankSieged)
+			.or_else(|| in_range.iter().min_by_key(|t| t.hits()));
+
+		match target {
+			Some(target) => {
+				ravager.command(AbilityId::EffectCorrosiveBile, Target::Pos(target.position()), false);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Burrows lurkers when enemies come close enough to hit, and unburrows them to follow the
+	/// army when nothing is near.
+	fn lurker_micro(&self, lurker: &Unit, targets: &Units) -> bool {
+		let closest = targets.iter().filter(|t| !t.is_flying()).closest(lurker);
+		let distance = closest.map_or(f32::INFINITY, |t| t.distance(lurker));
+
+		if lurker.is_burrowed() {
+			if distance > 2.0 * Self::LURKER_BURROW_RANGE {
+				lurker.use_ability(AbilityId::BurrowUpLurker, false);
+			}
+			// Burrowed lurkers attack on their own.
+			return true;
+		}
+
+		if distance <= Self::LURKER_BURROW_RANGE {
+			lurker.use_ability(AbilityId::BurrowDownLurker, false);
+			return true;
+		}
+
+		false
+	}
+
+	/// Steps a ranged unit back from enemies with less range than it while its weapon reloads.
+	fn kite(&self, unit: &Unit, targets: &Units) -> bool {
+		if !unit.on_cooldown() {
+			return false;
+		}
+
+		let threat = targets
+			.iter()
+			.filter(|t| t.can_attack_unit(unit))
+			.filter(|t| {
+				let (their_range, our_range) = if unit.is_flying() {
+					(t.air_range(), unit.air_range().max(unit.ground_range()))
+				} else {
+					(t.ground_range(), unit.ground_range().max(unit.air_range()))
+				};
+				their_range < our_range && t.distance(unit) < their_range + t.radius() + unit.radius() + 1.0
+			})
+			.closest(unit);
+
+		match threat {
+			Some(threat) => {
+				unit.move_to(Target::Pos(unit.position().towards(threat.position(), -Self::KITE_DISTANCE)), false);
+				true
+			}
+			None => false,
+		}
+	}
+}
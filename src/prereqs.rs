@@ -7,7 +7,7 @@ use crate::flourish_bot::FlourishBot;
 pub fn upgrade_prereqs(upgrade: UpgradeId) -> Option<(UnitTypeId, Vec<UnitTypeId>, Vec<UpgradeId>)> {
 	match upgrade {
     GlialReconstitution => Some((RoachWarren, vec![Lair], vec![])),
-    TunnelingClaws => Some((RoachWarren, vec![Lair], vec![])),
+    TunnelingClaws => Some((RoachWarren, vec![Lair], vec![Burrow])),
     ChitinousPlating => Some((UltraliskCavern, vec![], vec![])),
     ZergMeleeWeaponsLevel1 => Some((EvolutionChamber, vec![], vec![])),
     ZergMeleeWeaponsLevel2 => Some((EvolutionChamber, vec![Lair], vec![ZergMeleeWeaponsLevel1])),
@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_sc2::prelude::*;
+
+use crate::{flourish_bot::FlourishBot, targeting::TargetSelector};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueenRole {
+	/// Keeps injecting the townhall with this tag.
+	Injector(u64),
+	CreepSpreader,
+	/// Guards our bases, especially against air units.
+	Defender,
+	/// Follows the army to heal injured units.
+	Transfuser,
+}
+
+#[derive(Default)]
+pub struct QueenManager {
+	roles: HashMap<u64, QueenRole>,
+}
+
+impl QueenManager {
+	pub fn role(&self, queen: u64) -> Option<QueenRole> {
+		self.roles.get(&queen).copied()
+	}
+
+	fn count(&self, role: QueenRole) -> usize {
+		self.roles.values().filter(|r| **r == role).count()
+	}
+
+	/// Frees up a dead queen's role so another queen gets it.
+	pub fn queen_died(&mut self, queen: u64) {
+		self.roles.remove(&queen);
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to QueenManager while viewing
+// units.
+impl FlourishBot {
+	const CREEP_QUEENS: usize = 2;
+	/// Enemy air supply that each extra defending queen is meant to answer.
+	const AIR_SUPPLY_PER_DEFENDER: f32 = 4.0;
+	const MAX_DEFENDER_QUEENS: usize = 4;
+	/// Army supply we want before a queen goes along with it to transfuse.
+	const TRANSFUSER_ARMY_SUPPLY: u32 = 20;
+	/// Units under this share of their health are worth a transfuse.
+	const TRANSFUSE_HEALTH: f32 = 0.4;
+	const TRANSFUSE_RANGE: f32 = 7.0;
+	const TRANSFUSE_ENERGY: u32 = 50;
+	/// Units cheaper than this aren't worth 50 energy.
+	const TRANSFUSE_MIN_COST: u32 = 100;
+
+	/// How many queens we want in each role other than injector, which is one per townhall.
+	fn wanted_support_queens(&self) -> [(QueenRole, usize); 3] {
+		let air_supply = self.surveillance.air_threat_supply();
+		let defenders = ((air_supply / Self::AIR_SUPPLY_PER_DEFENDER).ceil() as usize).min(Self::MAX_DEFENDER_QUEENS);
+		let transfusers = if self.supply_army >= Self::TRANSFUSER_ARMY_SUPPLY { 1 } else { 0 };
+
+		[
+			(QueenRole::Defender, defenders),
+			(QueenRole::CreepSpreader, Self::CREEP_QUEENS),
+			(QueenRole::Transfuser, transfusers),
+		]
+	}
+
+	fn wanted_queens(&self) -> usize {
+		self.units.my.townhalls.ready().len()
+			+ self.wanted_support_queens().iter().map(|(_, n)| n).sum::<usize>()
+	}
+
+	/// Gives every queen a role, filling injectors first, and takes roles away from queens that
+	/// have died or whose base has.
+	fn assign_queen_roles(&mut self) {
+		let queens = self.units.my.units.of_type(UnitTypeId::Queen);
+		let townhalls = self.units.my.townhalls.ready();
+
+		self.queens.roles.retain(|tag, role| {
+			queens.get(*tag).is_some()
+				&& match role {
+					QueenRole::Injector(base) => townhalls.get(*base).is_some(),
+					_ => true,
+				}
+		});
+
+		let mut unassigned = queens.filter(|q| self.queens.role(q.tag()).is_none());
+		if unassigned.is_empty() {
+			return;
+		}
+
+		let injected = self
+			.queens
+			.roles
+			.values()
+			.filter_map(|r| match r {
+				QueenRole::Injector(base) => Some(*base),
+				_ => None,
+			})
+			.collect::<HashSet<u64>>();
+		for base in townhalls.iter().filter(|b| !injected.contains(&b.tag())) {
+			if let Some(queen) = unassigned.closest(base) {
+				let tag = queen.tag();
+				self.queens.roles.insert(tag, QueenRole::Injector(base.tag()));
+				unassigned.remove(tag);
+			}
+		}
+
+		for (role, wanted) in self.wanted_support_queens() {
+			while self.queens.count(role) < wanted {
+				match unassigned.pop() {
+					Some(queen) => {
+						self.queens.roles.insert(queen.tag(), role);
+					}
+					None => return,
+				}
+			}
+		}
+
+		// Anything left over helps defend.
+		for queen in &unassigned {
+			self.queens.roles.insert(queen.tag(), QueenRole::Defender);
+		}
+	}
+
+	/// Trains queens up to the number our roles call for, from the hatchery that most needs one.
+	pub fn train_queens(&mut self) {
+		let queen = UnitTypeId::Queen;
+		if self.counter().all().count(queen) >= self.wanted_queens() || !self.can_afford(queen, true) {
+			return;
+		}
+
+		let injected = self
+			.queens
+			.roles
+			.values()
+			.filter_map(|r| match r {
+				QueenRole::Injector(base) => Some(*base),
+				_ => None,
+			})
+			.collect::<HashSet<u64>>();
+		let idle_townhalls = self.units.my.townhalls.ready().filter(|t| t.is_idle());
+		let townhall = idle_townhalls
+			.iter()
+			.find(|t| !injected.contains(&t.tag()))
+			.or_else(|| idle_townhalls.first());
+
+		if let Some(townhall) = townhall {
+			townhall.train(queen, false);
+			self.subtract_resources(queen, true);
+		}
+	}
+
+	/// Transfuses badly hurt, expensive units near any queen with the energy for it. Returns the
+	/// tags of queens that were given a transfuse order.
+	fn transfuse(&self, queens: &Units) -> HashSet<u64> {
+		let mut transfused = HashSet::new();
+		let mut busy = HashSet::new();
+
+		let injured = self.units.my.units.filter(|u| {
+			!u.is_structure()
+				&& u.is_ready()
+				&& u.health_percentage().map_or(false, |h| h < Self::TRANSFUSE_HEALTH)
+				&& {
+					let cost = self.get_unit_cost(u.type_id());
+					cost.minerals + cost.vespene >= Self::TRANSFUSE_MIN_COST
+				}
+		});
+
+		for queen in queens.iter().filter(|q| q.energy().map_or(false, |e| e >= Self::TRANSFUSE_ENERGY)) {
+			if let Some(target) = injured
+				.iter()
+				.filter(|u| u.tag() != queen.tag() && !transfused.contains(&u.tag()))
+				.closer(Self::TRANSFUSE_RANGE, queen)
+				.min_by_key(|u| u.hits())
+			{
+				queen.command(AbilityId::TransfusionTransfusion, Target::Tag(target.tag()), false);
+				transfused.insert(target.tag());
+				busy.insert(queen.tag());
+			}
+		}
+
+		busy
+	}
+
+	/// Gives every queen orders according to its role.
+	pub fn queen_micro(&mut self) {
+		self.assign_queen_roles();
+
+		let queens = self.units.my.units.of_type(UnitTypeId::Queen);
+		if queens.is_empty() {
+			return;
+		}
+		let busy = self.transfuse(&queens);
+
+		let townhalls = self.units.my.townhalls.clone();
+		let base_threats = self
+			.units
+			.enemy
+			.units
+			.filter(|e| townhalls.iter().any(|h| h.is_closer(20.0, *e)));
+		let army_center = self
+			.units
+			.my
+			.units
+			.filter(|u| !u.is_worker() && u.type_id() != UnitTypeId::Queen && u.can_attack())
+			.center();
+
+		let mut selector = TargetSelector::new(&queens);
+		for queen in queens.iter().filter(|q| !busy.contains(&q.tag())) {
+			match self.queens.role(queen.tag()) {
+				Some(QueenRole::Injector(base)) => {
+					if let Some(townhall) = townhalls.get(base) {
+						self.inject_with_queen(queen, townhall);
+					}
+				}
+				Some(QueenRole::CreepSpreader) if self.spread_creep_with_queen(queen) => {}
+				Some(QueenRole::Defender) | Some(QueenRole::CreepSpreader) | None => {
+					if let Some(target) = self.choose_target(&mut selector, queen, &base_threats) {
+						queen.attack(Target::Tag(target.tag()), false);
+					} else if queen.is_idle() {
+						// Wait in the mineral line of the closest base, where harassment hits first.
+						if let Some(base) = townhalls.closest(queen) {
+							let minerals = self.units.mineral_fields.closer(11.0, base);
+							if let Some(center) = minerals.center() {
+								queen.move_to(Target::Pos(base.position().towards(center, 3.0)), false);
+							}
+						}
+					}
+				}
+				Some(QueenRole::Transfuser) => {
+					if let Some(center) = army_center {
+						if !queen.is_closer(6.0, center) {
+							queen.move_to(Target::Pos(center), false);
+						}
+					}
+				}
+			}
+		}
+	}
+}
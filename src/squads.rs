@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_sc2::prelude::*;
+
+use crate::{combat_sim::CombatDecision, flourish_bot::FlourishBot, targeting::TargetSelector};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SquadRole {
+	MainArmy,
+	/// Stays home to deal with attacks on our bases while the main army is away.
+	Defense,
+	Harass,
+	/// Newly made units on their way to join the main army.
+	Reinforcement,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SquadState {
+	/// Waiting at home for orders.
+	Idle,
+	Attacking,
+	Defending,
+	Retreating,
+}
+
+pub struct Squad {
+	pub role: SquadRole,
+	pub state: SquadState,
+	/// Where the squad is headed when it has nothing in sight to fight.
+	pub target: Option<Point2>,
+	/// Somewhere the squad goes first on the way to its target, to steer around enemies.
+	pub waypoint: Option<Point2>,
+	pub members: HashSet<u64>,
+	/// The target the squad's melee units last finished forming up to fight at. Cleared when the
+	/// squad disengages, heads somewhere else or has no enemies left in range.
+	pub formed_for: Option<Point2>,
+}
+
+impl Squad {
+	pub fn new(role: SquadRole) -> Self {
+		Self {
+			role,
+			state: SquadState::Idle,
+			target: None,
+			waypoint: None,
+			members: HashSet::new(),
+			formed_for: None,
+		}
+	}
+
+	/// The squad's members that are still alive, out of the given units.
+	pub fn units(&self, all: &Units) -> Units {
+		all.filter(|u| self.members.contains(&u.tag()))
+	}
+}
+
+pub struct ArmyManager {
+	pub squads: Vec<Squad>,
+	/// When the last harassment run ended, in seconds.
+	pub last_harass: f32,
+	/// What simulating the army against everything we know the enemy has last said.
+	pub fight_decision: CombatDecision,
+	/// The game loop that simulation ran on, if it has yet.
+	last_simulated: Option<u32>,
+}
+
+impl Default for ArmyManager {
+	fn default() -> Self {
+		Self {
+			squads: vec![
+				Squad::new(SquadRole::MainArmy),
+				Squad::new(SquadRole::Defense),
+				Squad::new(SquadRole::Reinforcement),
+				Squad::new(SquadRole::Harass),
+			],
+			last_harass: 0.0,
+			fight_decision: CombatDecision::Hold,
+			last_simulated: None,
+		}
+	}
+}
+
+impl ArmyManager {
+	/// The first squad with the given role.
+	pub fn squad(&self, role: SquadRole) -> Option<&Squad> {
+		self.squads.iter().find(|s| s.role == role)
+	}
+
+	pub fn squad_mut(&mut self, role: SquadRole) -> Option<&mut Squad> {
+		self.squads.iter_mut().find(|s| s.role == role)
+	}
+
+	/// Whether the unit with this tag is in any squad.
+	pub fn is_assigned(&self, tag: u64) -> bool {
+		self.squads.iter().any(|s| s.members.contains(&tag))
+	}
+
+	/// Moves a unit into the first squad with the given role, taking it out of any other squad.
+	pub fn assign(&mut self, tag: u64, role: SquadRole) {
+		self.remove_unit(tag);
+		if let Some(squad) = self.squad_mut(role) {
+			squad.members.insert(tag);
+		}
+	}
+
+	/// Moves every member of one squad into another.
+	pub fn merge(&mut self, from: SquadRole, into: SquadRole) {
+		let members = match self.squad_mut(from) {
+			Some(squad) => std::mem::take(&mut squad.members),
+			None => return,
+		};
+		if let Some(squad) = self.squad_mut(into) {
+			squad.members.extend(members);
+		}
+	}
+
+	pub fn remove_unit(&mut self, tag: u64) {
+		for squad in &mut self.squads {
+			squad.members.remove(&tag);
+		}
+	}
+
+	/// Whether the main army is out attacking.
+	pub fn is_attacking(&self) -> bool {
+		self.squad(SquadRole::MainArmy).map_or(false, |s| s.state == SquadState::Attacking)
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to ArmyManager while viewing units.
+impl FlourishBot {
+	/// Unit types that get put into squads, including their burrowed forms so units keep their
+	/// squad while underground.
+	const ARMY_UNIT_TYPES: &'static [UnitTypeId] = &[
+		UnitTypeId::Zergling,
+		UnitTypeId::ZerglingBurrowed,
+		UnitTypeId::Baneling,
+		UnitTypeId::BanelingBurrowed,
+		UnitTypeId::Roach,
+		UnitTypeId::RoachBurrowed,
+		UnitTypeId::Ravager,
+		UnitTypeId::RavagerBurrowed,
+		UnitTypeId::Hydralisk,
+		UnitTypeId::HydraliskBurrowed,
+		UnitTypeId::LurkerMP,
+		UnitTypeId::LurkerMPBurrowed,
+		UnitTypeId::Infestor,
+		UnitTypeId::InfestorBurrowed,
+		UnitTypeId::SwarmHostMP,
+		UnitTypeId::SwarmHostBurrowedMP,
+		UnitTypeId::Ultralisk,
+		UnitTypeId::UltraliskBurrowed,
+		UnitTypeId::Mutalisk,
+		UnitTypeId::Corruptor,
+		UnitTypeId::BroodLord,
+		UnitTypeId::Viper,
+	];
+	const START_ATTACK_THRESHOLD: usize = 20;
+	const END_ATTACK_THRESHOLD: usize = 3;
+	/// Reinforcements wait until there are this many of them before joining an attack.
+	const REINFORCEMENT_GROUP_SIZE: usize = 6;
+	/// Enemies this close to a townhall count as attacking it.
+	const BASE_DEFENSE_RADIUS: f32 = 25.0;
+	/// Enemies within this distance of a squad, seen in the last few seconds, are who it's
+	/// fighting.
+	const LOCAL_FIGHT_RADIUS: f32 = 15.0;
+	const LOCAL_FIGHT_SECONDS: f32 = 5.0;
+	/// A regroup point is unsafe if enemies were seen this close to it recently.
+	const REGROUP_SAFE_RADIUS: f32 = 15.0;
+	const REGROUP_ENEMY_SECONDS: f32 = 30.0;
+	/// A squad whose center is this close to where it's going has gathered there.
+	pub const REGROUP_RADIUS: f32 = 6.0;
+	/// Game loops between simulations of the whole army against everything we know the enemy has.
+	/// The answer only changes as armies grow, so there's no need to run it every step.
+	const ARMY_SIMULATION_DELAY: u32 = 22;
+
+	/// Our units that should be in a squad.
+	pub fn army_units(&self) -> Units {
+		self.units.my.units.filter(|u| Self::ARMY_UNIT_TYPES.contains(&u.type_id()))
+	}
+
+	/// Puts new army units into the reinforcements, and drops dead units from every squad.
+	fn assign_army_units(&mut self) {
+		let army = self.army_units();
+
+		for squad in &mut self.army.squads {
+			squad.members.retain(|tag| army.get(*tag).is_some());
+		}
+
+		for unit in &army {
+			if !self.army.is_assigned(unit.tag()) {
+				self.army.assign(unit.tag(), SquadRole::Reinforcement);
+			}
+		}
+	}
+
+	/// Decides what every squad should be doing this step.
+	fn update_squads(&mut self) {
+		self.assign_army_units();
+
+		let army = self.army_units();
+		let main_size = self.army.squad(SquadRole::MainArmy).map_or(0, |s| s.units(&army).len());
+		let reinforcement_size = self.army.squad(SquadRole::Reinforcement).map_or(0, |s| s.units(&army).len());
+
+		// Check if speed upgrade is >80% ready
+		let upgrades = vec![UpgradeId::Zerglingmovementspeed, UpgradeId::ZergMeleeWeaponsLevel1];
+		let upgrades_almost_ready = upgrades.iter().all(|upgrade| self.has_upgrade(*upgrade) || self.upgrade_progress(*upgrade) >= 0.8);
+		let attacking = self.army.is_attacking();
+
+		let home = self.start_location.towards(self.start_center, -8.0);
+		let main_center = self.army.squad(SquadRole::MainArmy).and_then(|s| s.units(&army).center());
+		let staging = self.staging_point(main_center.unwrap_or(home));
+
+		// Reinforcements join the main army straight away while it's home. Once it's left, they
+		// gather at the staging point and go together so they don't trickle into fights.
+		let reinforcements_gathered = self
+			.army
+			.squad(SquadRole::Reinforcement)
+			.and_then(|s| s.units(&army).center())
+			.map_or(false, |c| c.is_closer(Self::REGROUP_RADIUS, staging));
+		if !attacking || reinforcement_size >= Self::REINFORCEMENT_GROUP_SIZE && reinforcements_gathered {
+			self.army.merge(SquadRole::Reinforcement, SquadRole::MainArmy);
+		}
+
+		let army_size = if attacking { main_size } else { main_size + reinforcement_size };
+
+		// Pit the army we'd attack with against everything we know the enemy has.
+		let fighters = self
+			.army
+			.squads
+			.iter()
+			.filter(|s| s.role == SquadRole::MainArmy || !attacking && s.role == SquadRole::Reinforcement)
+			.flat_map(|s| s.units(&army))
+			.collect::<Vec<Unit>>();
+		let game_loop = self.state.observation.game_loop();
+		if self.army.last_simulated.map_or(true, |last| last + Self::ARMY_SIMULATION_DELAY <= game_loop) {
+			self.army.fight_decision = self
+				.simulate_fight(fighters.iter(), self.surveillance.remembered_enemy_units())
+				.decision();
+			self.army.last_simulated = Some(game_loop);
+		}
+		let decision = self.army.fight_decision;
+		let local_decision = self.army.squad(SquadRole::MainArmy).and_then(|s| self.local_fight(s, &army));
+		// Fights against things we can't see are never worth taking.
+		let undetected = main_center.map_or(false, |c| self.has_undetected_threat_near(c));
+
+		let should_attack = upgrades_almost_ready && army_size > Self::START_ATTACK_THRESHOLD && decision == CombatDecision::Engage
+			|| attacking && army_size > Self::END_ATTACK_THRESHOLD && decision != CombatDecision::Retreat;
+
+		let bases_attacked = self
+			.units
+			.my
+			.townhalls
+			.iter()
+			.any(|h| self.units.enemy.all.iter().any(|e| (!e.is_flying() || e.can_attack()) && h.is_closer(Self::BASE_DEFENSE_RADIUS, e)));
+
+		let attack_target = self.choose_attack_target(main_center.unwrap_or(home));
+		let regroup = self.regroup_point(main_center.unwrap_or(home));
+
+		if let Some(main) = self.army.squad_mut(SquadRole::MainArmy) {
+			let regrouped = main_center.map_or(true, |c| main.target.map_or(true, |t| c.is_closer(Self::REGROUP_RADIUS, t)));
+
+			if main.state == SquadState::Retreating && !regrouped {
+				// Keep running until the whole squad has made it back together.
+			} else if local_decision == Some(CombatDecision::Retreat)
+				|| undetected
+				|| main.state == SquadState::Attacking && !should_attack
+			{
+				main.state = SquadState::Retreating;
+				main.target = Some(regroup);
+			} else if should_attack {
+				main.state = SquadState::Attacking;
+				main.target = Some(attack_target);
+			} else {
+				main.state = SquadState::Defending;
+				main.target = Some(home);
+			}
+		}
+		let main_attacking = self.army.is_attacking();
+		self.update_harass();
+
+		// While the main army is away, anything waiting at home defends.
+		if main_attacking && bases_attacked {
+			self.army.merge(SquadRole::Reinforcement, SquadRole::Defense);
+		} else if !bases_attacked {
+			self.army.merge(SquadRole::Defense, if main_attacking { SquadRole::Reinforcement } else { SquadRole::MainArmy });
+		}
+
+		for squad in &mut self.army.squads {
+			match squad.role {
+				SquadRole::MainArmy | SquadRole::Harass => {}
+				SquadRole::Reinforcement if main_attacking => {
+					squad.state = SquadState::Idle;
+					squad.target = Some(staging);
+				}
+				_ => {
+					squad.state = SquadState::Defending;
+					squad.target = Some(home);
+				}
+			}
+		}
+	}
+
+	/// What the local combat estimate says about the fight a squad is in or about to be in, or
+	/// None if there's nothing near it.
+	pub fn local_fight(&self, squad: &Squad, army: &Units) -> Option<CombatDecision> {
+		let members = squad.units(army);
+		let center = members.center()?;
+		let game_loop = self.state.observation.game_loop();
+
+		let mut enemies = self
+			.surveillance
+			.enemies_seen_near(center, Self::LOCAL_FIGHT_RADIUS, Self::LOCAL_FIGHT_SECONDS, game_loop)
+			.collect::<Vec<&Unit>>();
+		enemies.extend(
+			self.surveillance
+				.remembered_enemy_structures()
+				.filter(|s| s.can_attack() && s.is_closer(Self::LOCAL_FIGHT_RADIUS, center)),
+		);
+		if enemies.is_empty() {
+			return None;
+		}
+
+		Some(self.simulate_fight(members.iter(), enemies.into_iter()).decision())
+	}
+
+	/// Whether enemy forces or static defense have been seen near a point recently.
+	fn is_point_unsafe(&self, point: Point2) -> bool {
+		let game_loop = self.state.observation.game_loop();
+		self.surveillance
+			.enemy_supply_seen_near(point, Self::REGROUP_SAFE_RADIUS, Self::REGROUP_ENEMY_SECONDS, game_loop)
+			> 0.0
+			|| self.surveillance.static_defense_near(point, Self::REGROUP_SAFE_RADIUS)
+	}
+
+	/// The closest spot to `from`, out of our bases and their defensive positions, that no enemy
+	/// forces have been seen near recently. Falls back to home if they all have.
+	pub fn regroup_point(&self, from: Point2) -> Point2 {
+		let home = self.start_location.towards(self.start_center, -8.0);
+		let mut candidates = self.units.my.townhalls.iter().map(|t| t.position()).collect::<Vec<Point2>>();
+		if let Some(terrain) = &self.terrain {
+			candidates.extend(
+				self.units
+					.my
+					.townhalls
+					.iter()
+					.filter_map(|t| terrain.defensive_position(t.position())),
+			);
+		}
+
+		candidates
+			.into_iter()
+			.filter(|p| !self.is_point_unsafe(*p))
+			.min_by_key(|p| p.distance_squared(from) as u32)
+			.unwrap_or(home)
+	}
+
+	/// Where reinforcements wait while the main army is out: the safe regroup point closest to
+	/// the army.
+	fn staging_point(&self, army_center: Point2) -> Point2 {
+		self.regroup_point(army_center)
+	}
+
+	/// Gives orders to every member of one squad based on its state. Returns whether its melee
+	/// units have formed up for the fight.
+	fn tick_squad(&self, squad: &Squad) -> bool {
+		let members = squad.units(&self.army_units());
+		if members.is_empty() {
+			return false;
+		}
+
+		let targets = match squad.state {
+			SquadState::Attacking if squad.role == SquadRole::Harass => self.harass_targets(&members),
+			SquadState::Attacking => self.units.enemy.all.clone(),
+			SquadState::Defending | SquadState::Idle => self
+				.units
+				.enemy
+				.all
+				.filter(|e| self.units.my.townhalls.iter().any(|h| h.is_closer(Self::BASE_DEFENSE_RADIUS, *e))),
+			SquadState::Retreating => Units::new(),
+		};
+
+		// While hunting for the last enemy buildings the main army splits up to cover more ground.
+		let hunt_targets = if self.base_hunt.active && squad.role == SquadRole::MainArmy && squad.state == SquadState::Attacking {
+			self.assign_hunt_groups(&members)
+		} else {
+			HashMap::new()
+		};
+
+		let (forming, formed) = self.melee_formation(&members, &targets, squad.formed_for.is_some());
+		let mut selector = TargetSelector::new(&members);
+		for u in &members {
+			if forming.contains(&u.tag()) || self.unit_micro(u, &targets) {
+				continue;
+			}
+
+			// Units that can't shoot anything here, like corruptors against ground, just stay
+			// with the squad.
+			if let Some(target) = self.choose_target(&mut selector, u, &targets) {
+				// Focus fire on what's in range, otherwise attack-move towards it so we fight
+				// whatever is on the way. Don't get stuck trying to kill changelings.
+				if target.type_id() == UnitTypeId::ChangelingZergling || u.in_range(target, 0.0) {
+					u.attack(Target::Tag(target.tag()), false);
+				} else {
+					u.attack(Target::Pos(target.position()), false);
+				}
+			} else if let Some(hunt_target) = hunt_targets.get(&u.tag()) {
+				u.attack(Target::Pos(*hunt_target), false);
+			} else if let Some(target) = squad.waypoint.or(squad.target) {
+				u.move_to(Target::Pos(target), false);
+			}
+		}
+
+		formed
+	}
+
+	/// Updates every squad and has its members act on it.
+	pub fn army_micro(&mut self) {
+		self.update_squads();
+
+		for squad in &mut self.army.squads {
+			let disengaged = matches!(squad.state, SquadState::Retreating | SquadState::Idle);
+			if disengaged || squad.formed_for != squad.target {
+				squad.formed_for = None;
+			}
+		}
+
+		let formed = self.army.squads.iter().map(|s| self.tick_squad(s)).collect::<Vec<bool>>();
+		for (squad, formed) in self.army.squads.iter_mut().zip(formed) {
+			squad.formed_for = if formed { squad.target } else { None };
+		}
+	}
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+/// How many crawlers of each kind we want at one base.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub struct CrawlerTargets {
+	pub spores: usize,
+	pub spines: usize,
+}
+
+#[derive(Default)]
+pub struct StaticDefensePlan {
+	/// Crawlers wanted at each of our bases, keyed by townhall tag. Bases that don't need any
+	/// aren't in the plan.
+	targets: HashMap<u64, CrawlerTargets>,
+}
+
+impl StaticDefensePlan {
+	pub fn targets(&self, base: u64) -> CrawlerTargets {
+		self.targets.get(&base).copied().unwrap_or_default()
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to StaticDefensePlan while viewing
+// units.
+impl FlourishBot {
+	/// Crawlers this close to a townhall count as defending it.
+	const CRAWLER_RADIUS: f32 = 14.0;
+	/// Enemy air supply we answer with a spore in every mineral line.
+	const AIR_SUPPLY_PER_SPORE: f32 = 6.0;
+	const MAX_SPORES_PER_BASE: usize = 2;
+	/// Before this many seconds, a ground army bigger than ours is answered with spines.
+	const EARLY_AGGRESSION_TIME: f32 = 6.0 * 60.0;
+	/// Enemy supply over our own that each spine makes up for.
+	const SUPPLY_PER_SPINE: u32 = 4;
+	const MAX_SPINES: usize = 4;
+
+	/// Works out how many spores and spines each base needs from what we've scouted.
+	fn update_static_defense_plan(&mut self) {
+		let air_supply = self.surveillance.air_threat_supply();
+		let cloak = self.surveillance.has_cloak_threat();
+
+		let spores = if air_supply <= 0.0 && !cloak {
+			0
+		} else {
+			((air_supply / Self::AIR_SUPPLY_PER_SPORE).ceil() as usize).clamp(1, Self::MAX_SPORES_PER_BASE)
+		};
+
+		let enemy_supply = self.surveillance.rounded_enemy_supply();
+		let spines = if self.time < Self::EARLY_AGGRESSION_TIME && enemy_supply > self.supply_army {
+			(((enemy_supply - self.supply_army) / Self::SUPPLY_PER_SPINE) as usize).min(Self::MAX_SPINES)
+		} else {
+			0
+		};
+
+		// Spines go at the natural if we have one, since that's where an early push arrives.
+		let natural = self.terrain.as_ref().and_then(|t| t.natural);
+		let spine_base = natural
+			.and_then(|n| self.units.my.townhalls.iter().find(|t| t.is_closer(1.0, n)))
+			.or_else(|| self.units.my.townhalls.closest(self.start_location))
+			.map(|t| t.tag());
+
+		let mut targets = HashMap::new();
+		for base in self.units.my.townhalls.ready().iter() {
+			let base_targets = CrawlerTargets {
+				spores,
+				spines: if Some(base.tag()) == spine_base { spines } else { 0 },
+			};
+			if base_targets != CrawlerTargets::default() {
+				targets.insert(base.tag(), base_targets);
+			}
+		}
+
+		self.static_defense.targets = targets;
+	}
+
+	/// Builds the crawlers the plan calls for at the spots reserved for them. Crawlers already
+	/// going up are left to finish even if the plan has dropped them, since the plan moves with
+	/// army supply and cancelling throws away a quarter of the cost each time.
+	pub fn static_defense(&mut self) {
+		self.update_static_defense_plan();
+
+		let spawning_pool_ready = self
+			.units
+			.my
+			.structures
+			.iter()
+			.of_type(UnitTypeId::SpawningPool)
+			.ready()
+			.any(|_| true);
+		if !spawning_pool_ready {
+			return;
+		}
+
+		let mineral_tags = self
+			.units
+			.mineral_fields
+			.iter()
+			.map(|u| u.tag())
+			.collect::<Vec<u64>>();
+
+		for base in self.units.my.townhalls.ready().iter() {
+			let targets = self.static_defense.targets(base.tag());
+			let layout_spots = match self.layout_at(base.position()) {
+				Some(layout) => (layout.spores.clone(), layout.spines.clone()),
+				None => continue,
+			};
+
+			for (crawler, wanted, spots) in [
+				(UnitTypeId::SporeCrawler, targets.spores, layout_spots.0),
+				(UnitTypeId::SpineCrawler, targets.spines, layout_spots.1),
+			] {
+				let existing = self
+					.units
+					.my
+					.structures
+					.iter()
+					.of_type(crawler)
+					.closer(Self::CRAWLER_RADIUS, base)
+					.count();
+				let ordered = self
+					.units
+					.my
+					.workers
+					.iter()
+					.filter(|w| w.is_constructing() && w.target_pos().map_or(false, |p| spots.iter().any(|s| s.is_closer(1.0, p))))
+					.count();
+
+				if existing + ordered >= wanted || !self.can_afford(crawler, false) {
+					continue;
+				}
+
+				let spot = spots
+					.iter()
+					.find(|s| self.has_creep(**s) && self.can_place(crawler, **s))
+					.copied();
+				if let Some(spot) = spot {
+					if let Some(builder) = self.get_builder(spot, &mineral_tags) {
+						builder.build(crawler, spot, false);
+						self.subtract_resources(crawler, false);
+					}
+				}
+			}
+		}
+	}
+}
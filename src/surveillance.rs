@@ -1,25 +1,170 @@
 use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 
 use priority_queue::PriorityQueue;
-use rust_sc2::{ids::UnitTypeId, unit::Unit, units::AllUnits, game_data::Cost, consts::{GAME_SPEED, FRAMES_PER_SECOND}};
+use rust_sc2::{ids::UnitTypeId, unit::Unit, units::AllUnits, game_data::Cost, geometry::Point2, distance::Distance, consts::{GAME_SPEED, FRAMES_PER_SECOND}};
 
 use crate::flourish_bot::FlourishBot;
 
 #[derive(Default)]
 pub struct Surveillance {
 	enemy_units: PriorityQueue<(u64, UnitTypeId), Reverse<u32>>,
-	enemy_army_supply: f32
+	/// The last sighting of each enemy army unit, and the game loop it was seen on.
+	last_seen: HashMap<u64, (Unit, u32)>,
+	/// The last sighting of each enemy structure, and the game loop it was seen on.
+	structures: HashMap<u64, (Unit, u32)>,
+	enemy_army_supply: f32,
+	/// The game loop each expansion, in the order of the bot's expansion list, was last in vision.
+	expansions_scouted: Vec<Option<u32>>,
+	/// Type and share of health left of each of our units last step, to notice damage we can't
+	/// explain.
+	our_health: HashMap<u64, (UnitTypeId, f32)>,
+	/// Unexplained damage that hasn't been backed up by more yet, and the game loop it happened on.
+	unconfirmed_attacks: Vec<(Point2, u32)>,
+	/// Our units that lost health since last step.
+	damaged: HashSet<u64>,
+	/// Where and on which game loop our units took damage with no visible enemy around to do it.
+	unseen_attacks: Vec<(Point2, u32)>,
 }
 
 impl Surveillance {
 	const TIME_TILL_REMOVE: f32 = 3.0 * 60.0;
+	/// Damage to our units with no visible enemy this close is from something we can't see.
+	const UNSEEN_DAMAGE_RADIUS: f32 = 14.0;
+	/// Enemies seen this recently still explain damage after dropping out of vision, like siege
+	/// tanks and tempests firing from the fog or units on high ground.
+	const UNSEEN_DAMAGE_MEMORY: f32 = 5.0;
+	/// Unexplained damage only counts once more of it happens nearby within this many seconds, so
+	/// a one-off health drop we can't account for doesn't look like a cloaked attacker.
+	const UNSEEN_DAMAGE_CONFIRM_SECONDS: f32 = 3.0;
+
+	/// Game loops in the given number of seconds. Every sighting is stamped with the game loop it
+	/// happened on, so all memory durations go through this.
+	fn loops(seconds: f32) -> u32 {
+		(seconds * FRAMES_PER_SECOND) as u32
+	}
 
 	pub fn observed_enemy_unit_die(&mut self, unit: Unit) {
+		self.last_seen.remove(&unit.tag());
+		self.structures.remove(&unit.tag());
 		if let Some(_) = self.enemy_units.remove(&(unit.tag(), unit.type_id())) {
 			self.enemy_army_supply -= unit.supply_cost()
 		}
 	}
 
+	/// Supply of remembered enemy flying units that can attack our ground units and drones.
+	pub fn air_threat_supply(&self) -> f32 {
+		self.remembered_enemy_units()
+			.filter(|u| u.is_flying() && u.can_attack_ground())
+			.map(|u| u.supply_cost())
+			.sum()
+	}
+
+	/// Whether we've seen big, tough air units that hydralisks and queens struggle against, and
+	/// that need corruptors instead.
+	pub fn has_seen_capital_ships(&self) -> bool {
+		self.remembered_enemy_units().any(|u| {
+			matches!(
+				u.type_id(),
+				UnitTypeId::Carrier
+					| UnitTypeId::Tempest
+					| UnitTypeId::Mothership
+					| UnitTypeId::Battlecruiser
+					| UnitTypeId::BroodLord
+			)
+		})
+	}
+
+	/// Whether we've seen any enemy units that can attack while cloaked or burrowed.
+	pub fn has_seen_cloaked_units(&self) -> bool {
+		self.remembered_enemy_units().any(|u| {
+			u.is_cloaked()
+				|| matches!(
+					u.type_id(),
+					UnitTypeId::DarkTemplar
+						| UnitTypeId::Banshee
+						| UnitTypeId::LurkerMP
+						| UnitTypeId::LurkerMPBurrowed
+						| UnitTypeId::WidowMine
+						| UnitTypeId::WidowMineBurrowed
+						| UnitTypeId::Observer
+						| UnitTypeId::ObserverSiegeMode
+				)
+		})
+	}
+
+	/// Whether we should expect cloaked or burrowed attackers: we've seen some, scouted the tech
+	/// for them, or had units take damage from something we couldn't see.
+	pub fn has_cloak_threat(&self) -> bool {
+		self.has_seen_cloaked_units()
+			|| !self.unseen_attacks.is_empty()
+			|| self.remembered_enemy_structures().any(|s| {
+				matches!(
+					s.type_id(),
+					UnitTypeId::DarkShrine | UnitTypeId::StarportTechLab | UnitTypeId::LurkerDenMP
+				)
+			})
+	}
+
+	/// Whether our units took damage from something unseen within `radius` of `point` in the last
+	/// few seconds.
+	pub fn unseen_attack_near(&self, point: Point2, radius: f32, seconds: f32, current_loop: u32) -> bool {
+		let since = current_loop.saturating_sub(Self::loops(seconds));
+		self.unseen_attacks
+			.iter()
+			.any(|(position, seen)| *seen >= since && position.is_closer(radius, point))
+	}
+
+	/// Whether our unit with this tag lost health since last step.
+	pub fn was_damaged(&self, tag: u64) -> bool {
+		self.damaged.contains(&tag)
+	}
+
+	/// Every enemy structure we've seen and haven't seen die, as it was when we last saw it.
+	pub fn remembered_enemy_structures(&self) -> impl Iterator<Item = &Unit> {
+		self.structures.values().map(|(structure, _)| structure)
+	}
+
+	/// Whether we know of enemy static defense that can hit ground units within `radius` of `point`,
+	/// or that would detect things placed there.
+	pub fn static_defense_near(&self, point: Point2, radius: f32) -> bool {
+		self.remembered_enemy_structures().any(|s| {
+			(s.can_attack_ground() || s.is_detector()) && s.is_closer(radius, point)
+		})
+	}
+
+	/// Every enemy army unit we think is still alive, as it was when we last saw it.
+	pub fn remembered_enemy_units(&self) -> impl Iterator<Item = &Unit> {
+		self.last_seen.values().map(|(unit, _)| unit)
+	}
+
+	/// Enemy army units last seen at most `seconds` ago.
+	pub fn enemies_seen_since(&self, seconds: f32, current_loop: u32) -> impl Iterator<Item = &Unit> {
+		let since = current_loop.saturating_sub(Self::loops(seconds));
+		self.last_seen
+			.values()
+			.filter(move |(_, seen)| *seen >= since)
+			.map(|(unit, _)| unit)
+	}
+
+	/// Enemy army units last seen within `radius` of `point` at most `seconds` ago.
+	pub fn enemies_seen_near(&self, point: Point2, radius: f32, seconds: f32, current_loop: u32) -> impl Iterator<Item = &Unit> {
+		self.enemies_seen_since(seconds, current_loop)
+			.filter(move |unit| unit.is_closer(radius, point))
+	}
+
+	/// Supply of enemy army units last seen near a point in the last few seconds.
+	pub fn enemy_supply_seen_near(&self, point: Point2, radius: f32, seconds: f32, current_loop: u32) -> f32 {
+		self.enemies_seen_near(point, radius, seconds, current_loop)
+			.map(|u| u.supply_cost())
+			.sum()
+	}
+
+	/// The game loop the expansion at this index was last in vision, if it ever has been.
+	pub fn expansion_last_scouted(&self, expansion: usize) -> Option<u32> {
+		self.expansions_scouted.get(expansion).copied().flatten()
+	}
+
 	pub fn rounded_enemy_supply(&self) -> u32 {
 		self.enemy_army_supply.ceil() as u32
 	}
@@ -31,23 +176,125 @@ impl FlourishBot {
 	/// Update Surveillance's record of all observed enemy army units to include ones that have just
 	/// been seen, and remove ones that haven't been seen in a while. 
 	pub fn update_enemy_units(&mut self) {
-		let current_step = self.game_step();
+		// Stamped with the game loop rather than game_step(), which is how many loops each step
+		// advances and never changes, so nothing seen would ever have been forgotten.
+		let current_step = self.state.observation.game_loop();
+
+		for structure in self.units.enemy.structures.clone().iter() {
+			self.surveillance.structures.insert(structure.tag(), (structure.clone(), current_step));
+		}
+
+		// Structures that should be in vision but aren't have been destroyed without us seeing it.
+		let gone = self
+			.surveillance
+			.structures
+			.iter()
+			.filter(|(tag, (structure, _))| self.is_visible(structure.position()) && self.units.enemy.structures.get(**tag).is_none())
+			.map(|(tag, _)| *tag)
+			.collect::<Vec<u64>>();
+		for tag in gone {
+			self.surveillance.structures.remove(&tag);
+		}
+
+		let scouted = self.expansions.iter().map(|e| self.is_visible(e.loc)).collect::<Vec<bool>>();
+		self.surveillance.expansions_scouted.resize(scouted.len(), None);
+		for (last, visible) in self.surveillance.expansions_scouted.iter_mut().zip(scouted) {
+			if visible {
+				*last = Some(current_step);
+			}
+		}
 
 		for unit in self.units.enemy.units.clone().iter().filter(|u| !u.is_worker()) {
+			self.surveillance.last_seen.insert(unit.tag(), (unit.clone(), current_step));
 			if let None = self.surveillance.enemy_units.push((unit.tag(), unit.type_id()), Reverse(current_step)) {
 				// if this unit hasn't been seen before at all:
 				self.surveillance.enemy_army_supply += unit.supply_cost();
 			}
 		}
 
+		self.detect_unseen_damage(current_step);
+
 		while let Some(((tag, unit_type), Reverse(last_seen))) = self.surveillance.enemy_units.pop() {
-			if last_seen + (Surveillance::TIME_TILL_REMOVE * FRAMES_PER_SECOND) as u32 > current_step {
+			if last_seen + Surveillance::loops(Surveillance::TIME_TILL_REMOVE) > current_step {
 				// if the unit was seen some time in the last 3 minutes, assume it hasn't died randomly.
 				self.surveillance.enemy_units.push((tag, unit_type), Reverse(last_seen));
 				break;
 			}
 
+			self.surveillance.last_seen.remove(&tag);
 			self.surveillance.enemy_army_supply -= self.get_unit_cost(unit_type).supply
 		}
 	}
-}
\ No newline at end of file
+
+	/// Records where our units lost health with no visible enemy near enough to have done it,
+	/// which means something cloaked or burrowed is attacking.
+	fn detect_unseen_damage(&mut self, current_step: u32) {
+		let mut unseen = Vec::new();
+		let mut health = HashMap::new();
+		let mut damaged_tags = HashSet::new();
+
+		for unit in self.units.my.all.iter().filter(|u| u.is_ready()) {
+			// As a share of the maximum, so burrowing, morphing and upgrades that change it don't
+			// look like damage.
+			let share = match unit.hits_max() {
+				Some(max) if max > 0 => unit.hits().unwrap_or(0) as f32 / max as f32,
+				_ => continue,
+			};
+			health.insert(unit.tag(), (unit.type_id(), share));
+
+			let damaged = self
+				.surveillance
+				.our_health
+				.get(&unit.tag())
+				.map_or(false, |(before_type, before)| *before_type == unit.type_id() && share < *before);
+			let could_have_hit = |e: &Unit| {
+				!(e.is_cloaked() && !e.is_revealed())
+					&& (e.can_attack_unit(unit) || Self::is_damage_caster(e.type_id()))
+			};
+			let explained = self.units.enemy.all.iter().any(|e| could_have_hit(e) && e.is_closer(Surveillance::UNSEEN_DAMAGE_RADIUS, unit))
+				|| self
+					.surveillance
+					.enemies_seen_near(unit.position(), Surveillance::UNSEEN_DAMAGE_RADIUS, Surveillance::UNSEEN_DAMAGE_MEMORY, current_step)
+					.any(|e| could_have_hit(e))
+				|| self.surveillance.remembered_enemy_structures().any(|s| could_have_hit(s) && s.is_closer(Surveillance::UNSEEN_DAMAGE_RADIUS, unit));
+			if damaged {
+				damaged_tags.insert(unit.tag());
+			}
+			if damaged && !explained {
+				unseen.push((unit.position(), current_step));
+			}
+		}
+
+		self.surveillance.our_health = health;
+		self.surveillance.damaged = damaged_tags;
+
+		// Only take unexplained damage seriously once it's happened on more than one step close by.
+		let confirm_since = current_step.saturating_sub(Surveillance::loops(Surveillance::UNSEEN_DAMAGE_CONFIRM_SECONDS));
+		self.surveillance.unconfirmed_attacks.retain(|(_, seen)| *seen >= confirm_since);
+		for (position, seen) in unseen {
+			let confirmed = self.surveillance.unconfirmed_attacks.iter().any(|(earlier, earlier_seen)| {
+				*earlier_seen < seen && earlier.is_closer(Surveillance::UNSEEN_DAMAGE_RADIUS, position)
+			});
+			if confirmed {
+				self.surveillance.unseen_attacks.push((position, seen));
+			}
+			self.surveillance.unconfirmed_attacks.push((position, seen));
+		}
+		// Forgotten on the same schedule as the enemy units we've seen.
+		let since = current_step.saturating_sub(Surveillance::loops(Surveillance::TIME_TILL_REMOVE));
+		self.surveillance.unseen_attacks.retain(|(_, seen)| *seen >= since);
+	}
+
+	/// Units that do damage with spells rather than a weapon, so can_attack won't show it.
+	fn is_damage_caster(unit_type: UnitTypeId) -> bool {
+		matches!(
+			unit_type,
+			UnitTypeId::HighTemplar
+				| UnitTypeId::Disruptor
+				| UnitTypeId::DisruptorPhased
+				| UnitTypeId::Infestor
+				| UnitTypeId::Raven
+				| UnitTypeId::WidowMineBurrowed
+		)
+	}
+}
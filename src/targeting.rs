@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+/// Keeps track of who our units are shooting during one step, so they spread their damage
+/// instead of all piling onto a unit that's already dead.
+pub struct TargetSelector {
+	/// Damage our units have already been told to deal to each enemy this step.
+	committed: HashMap<u64, f32>,
+	/// Share of our units that fly, used to weigh how dangerous each enemy is to us.
+	air_share: f32,
+}
+
+impl TargetSelector {
+	/// A selector for a step's worth of orders to the given units.
+	pub fn new(our_units: &Units) -> Self {
+		let air_share = if our_units.is_empty() {
+			0.0
+		} else {
+			our_units.iter().filter(|u| u.is_flying()).count() as f32 / our_units.len() as f32
+		};
+		Self {
+			committed: HashMap::new(),
+			air_share,
+		}
+	}
+
+	pub fn committed(&self, target: u64) -> f32 {
+		self.committed.get(&target).copied().unwrap_or(0.0)
+	}
+
+	pub fn commit(&mut self, target: u64, damage: f32) {
+		*self.committed.entry(target).or_insert(0.0) += damage;
+	}
+}
+
+// Doing this in FlourishBot so unit costs and weapons can be looked up from the game data.
+impl FlourishBot {
+	/// How much one point of dps is worth next to one resource of unit value when scoring targets.
+	const TARGET_VALUE_PER_DPS: f32 = 10.0;
+	/// Every this many distance units a target is out of range halves how much we want it.
+	const TARGET_DISTANCE_FALLOFF: f32 = 2.0;
+
+	/// Extra weight for units that are far more dangerous or important than their dps and cost
+	/// make them look.
+	fn special_target_priority(unit_type: UnitTypeId) -> f32 {
+		match unit_type {
+			UnitTypeId::SiegeTank | UnitTypeId::SiegeTankSieged | UnitTypeId::HighTemplar => 3.0,
+			UnitTypeId::Baneling | UnitTypeId::Disruptor | UnitTypeId::WidowMine | UnitTypeId::WidowMineBurrowed => 2.5,
+			UnitTypeId::Medivac
+			| UnitTypeId::Infestor
+			| UnitTypeId::InfestorBurrowed
+			| UnitTypeId::LurkerMPBurrowed
+			| UnitTypeId::Colossus => 2.0,
+			UnitTypeId::Overseer | UnitTypeId::ObserverSiegeMode | UnitTypeId::Raven => 1.5,
+			_ => 1.0,
+		}
+	}
+
+	/// How much `attacker` wants to shoot `target`, or None if it can't.
+	/// Weighs how much damage the target does to our army, what it's worth, how close it is to
+	/// dying once the damage already headed its way lands, and how far out of range it is.
+	pub fn target_score(&self, selector: &TargetSelector, attacker: &Unit, target: &Unit, avoid_overkill: bool) -> Option<f32> {
+		if !attacker.can_attack_unit(target) {
+			return None;
+		}
+
+		let remaining = target.hits().unwrap_or(0) as f32 - if avoid_overkill { selector.committed(target.tag()) } else { 0.0 };
+		if remaining <= 0.0 {
+			return None;
+		}
+
+		let threat = target.ground_dps() * (1.0 - selector.air_share) + target.air_dps() * selector.air_share;
+		let cost = self.get_unit_cost(target.type_id());
+		let value = (cost.minerals + cost.vespene) as f32 / Self::TARGET_VALUE_PER_DPS;
+		let priority = Self::special_target_priority(target.type_id());
+
+		let range = if target.is_flying() { attacker.air_range() } else { attacker.ground_range() };
+		let gap = (attacker.distance(target) - attacker.radius() - target.radius() - range).max(0.0);
+
+		Some((threat + value + 1.0) * priority / remaining.max(1.0) / (1.0 + gap / Self::TARGET_DISTANCE_FALLOFF))
+	}
+
+	/// The best target for `attacker` out of `targets`, counting the damage it'll do against
+	/// that target as committed. Targets already getting enough damage to die are only chosen
+	/// when there's nothing else.
+	pub fn choose_target<'a>(&self, selector: &mut TargetSelector, attacker: &Unit, targets: &'a Units) -> Option<&'a Unit> {
+		let best = |avoid_overkill: bool| {
+			targets
+				.iter()
+				.filter_map(|t| self.target_score(selector, attacker, t, avoid_overkill).map(|s| (t, s)))
+				.max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+				.map(|(t, _)| t)
+		};
+		let target = best(true).or_else(|| best(false))?;
+
+		let damage = self.combat_unit(attacker).damage_against(&self.combat_unit(target));
+		selector.commit(target.tag(), damage);
+		Some(target)
+	}
+}
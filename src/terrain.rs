@@ -0,0 +1,562 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::rc::Rc;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+pub type Tile = (usize, usize);
+
+/// A rectangular grid of map tiles, each of which is either set or not.
+#[derive(Clone)]
+pub struct Grid {
+	width: usize,
+	height: usize,
+	cells: Vec<bool>,
+}
+
+impl Grid {
+	pub fn new(width: usize, height: usize) -> Self {
+		Self {
+			width,
+			height,
+			cells: vec![false; width * height],
+		}
+	}
+
+	pub fn from_fn(width: usize, height: usize, f: impl Fn(usize, usize) -> bool) -> Self {
+		let mut grid = Self::new(width, height);
+		for y in 0..height {
+			for x in 0..width {
+				grid.set((x, y), f(x, y));
+			}
+		}
+		grid
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	/// Whether the tile is set. Tiles off the edge of the grid never are.
+	pub fn get(&self, (x, y): Tile) -> bool {
+		x < self.width && y < self.height && self.cells[y * self.width + x]
+	}
+
+	pub fn set(&mut self, (x, y): Tile, value: bool) {
+		if x < self.width && y < self.height {
+			self.cells[y * self.width + x] = value;
+		}
+	}
+
+	fn index(&self, (x, y): Tile) -> usize {
+		y * self.width + x
+	}
+
+	/// The tile a point on the map lies in, if it's on the grid.
+	pub fn tile_at(&self, point: Point2) -> Option<Tile> {
+		if point.x < 0.0 || point.y < 0.0 {
+			return None;
+		}
+		let tile = (point.x as usize, point.y as usize);
+		if tile.0 < self.width && tile.1 < self.height {
+			Some(tile)
+		} else {
+			None
+		}
+	}
+
+	/// The on-grid neighbours of a tile, including diagonals.
+	fn neighbours8(&self, (x, y): Tile) -> impl Iterator<Item = Tile> + '_ {
+		(-1isize..=1)
+			.flat_map(|dy| (-1isize..=1).map(move |dx| (dx, dy)))
+			.filter(|&(dx, dy)| dx != 0 || dy != 0)
+			.filter_map(move |(dx, dy)| {
+				let nx = x as isize + dx;
+				let ny = y as isize + dy;
+				if nx >= 0 && ny >= 0 && (nx as usize) < self.width && (ny as usize) < self.height {
+					Some((nx as usize, ny as usize))
+				} else {
+					None
+				}
+			})
+	}
+
+	/// The closest set tile to a point, searching outwards up to the given number of tiles.
+	pub fn nearest_set(&self, point: Point2, max_radius: usize) -> Option<Tile> {
+		let (cx, cy) = (point.x.max(0.0) as isize, point.y.max(0.0) as isize);
+		(0..=max_radius as isize).find_map(|r| {
+			(-r..=r)
+				.flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+				.filter(|&(dx, dy)| dx.abs() == r || dy.abs() == r)
+				.map(|(dx, dy)| (cx + dx, cy + dy))
+				.filter(|&(x, y)| x >= 0 && y >= 0 && self.get((x as usize, y as usize)))
+				.map(|(x, y)| (x as usize, y as usize))
+				.min_by(|a, b| {
+					tile_center(*a).distance_squared(point)
+						.partial_cmp(&tile_center(*b).distance_squared(point))
+						.unwrap_or(Ordering::Equal)
+				})
+		})
+	}
+}
+
+/// The point in the middle of a tile.
+pub fn tile_center((x, y): Tile) -> Point2 {
+	Point2::new(x as f32 + 0.5, y as f32 + 0.5)
+}
+
+fn center_of(tiles: &[Tile]) -> Point2 {
+	let sum = tiles
+		.iter()
+		.fold(Point2::new(0.0, 0.0), |acc, t| acc + tile_center(*t));
+	sum / tiles.len() as f32
+}
+
+/// A connected area of buildable ground.
+pub struct Region {
+	pub tiles: Vec<Tile>,
+	pub center: Point2,
+}
+
+/// A connected area of walkable but unbuildable ground joining two or more regions, i.e. a ramp
+/// or a narrow passage.
+pub struct Choke {
+	pub tiles: Vec<Tile>,
+	pub center: Point2,
+	/// Indices into TerrainAnalysis::regions of the regions this choke connects.
+	pub regions: Vec<usize>,
+}
+
+/// Ground travel distance from one point to every tile on the map.
+pub struct DistanceField {
+	width: usize,
+	distances: Vec<f32>,
+}
+
+impl DistanceField {
+	/// Ground distance to the given tile, or None if it can't be reached.
+	pub fn get(&self, (x, y): Tile) -> Option<f32> {
+		self.distances
+			.get(y * self.width + x)
+			.copied()
+			.filter(|d| d.is_finite())
+	}
+}
+
+#[derive(PartialEq)]
+struct Frontier(f32, Tile);
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Frontier {
+	// Reversed so BinaryHeap pops the closest tile first.
+	fn cmp(&self, other: &Self) -> Ordering {
+		other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+	}
+}
+
+pub struct TerrainAnalysis {
+	pathing: Grid,
+	placement: Grid,
+	/// Which region (index into regions) each tile belongs to, if any.
+	region_map: Vec<Option<usize>>,
+	pub regions: Vec<Region>,
+	pub chokes: Vec<Choke>,
+	/// Index into chokes of the ramp out of our main.
+	pub main_ramp: Option<usize>,
+	pub natural: Option<Point2>,
+	/// For each base location, where units defending it should stand.
+	pub defensive_positions: Vec<(Point2, Point2)>,
+	/// Distance fields already worked out, by the tile they were measured from.
+	fields: RefCell<HashMap<Tile, Rc<DistanceField>>>,
+}
+
+impl TerrainAnalysis {
+	/// Regions smaller than this many tiles are ignored as noise.
+	const MIN_REGION_SIZE: usize = 16;
+	/// How far from the start location to look for a walkable tile, since the townhall blocks it.
+	const SEARCH_RADIUS: usize = 8;
+	/// How many tiles along the path towards the enemy a base's defensive position is.
+	const DEFENSIVE_DISTANCE: f32 = 7.0;
+	/// How many distance fields we keep around before starting the cache over.
+	const MAX_CACHED_FIELDS: usize = 64;
+
+	/// Analyses a map from its pathing and placement grids. `bases` are the expansion locations,
+	/// which should include both start locations.
+	pub fn new(pathing: Grid, placement: Grid, start: Point2, enemy_start: Point2, bases: &[Point2]) -> Self {
+		let buildable = Grid::from_fn(pathing.width(), pathing.height(), |x, y| {
+			pathing.get((x, y)) && placement.get((x, y))
+		});
+		let unbuildable = Grid::from_fn(pathing.width(), pathing.height(), |x, y| {
+			pathing.get((x, y)) && !placement.get((x, y))
+		});
+
+		let mut region_map = vec![None; pathing.width() * pathing.height()];
+		let mut regions = Vec::new();
+		for tiles in Self::components(&buildable) {
+			if tiles.len() < Self::MIN_REGION_SIZE {
+				continue;
+			}
+			for tile in &tiles {
+				region_map[buildable.index(*tile)] = Some(regions.len());
+			}
+			regions.push(Region {
+				center: center_of(&tiles),
+				tiles,
+			});
+		}
+
+		let mut chokes = Vec::new();
+		for tiles in Self::components(&unbuildable) {
+			let mut touching = tiles
+				.iter()
+				.flat_map(|t| pathing.neighbours8(*t))
+				.filter_map(|n| region_map[pathing.index(n)])
+				.collect::<Vec<usize>>();
+			touching.sort_unstable();
+			touching.dedup();
+
+			if touching.len() >= 2 {
+				chokes.push(Choke {
+					center: center_of(&tiles),
+					tiles,
+					regions: touching,
+				});
+			}
+		}
+
+		let mut analysis = Self {
+			pathing,
+			placement,
+			region_map,
+			regions,
+			chokes,
+			main_ramp: None,
+			natural: None,
+			defensive_positions: Vec::new(),
+			fields: RefCell::new(HashMap::new()),
+		};
+
+		let main_region = analysis.region_near(start);
+		analysis.main_ramp = analysis
+			.chokes
+			.iter()
+			.enumerate()
+			.filter(|(_, c)| main_region.map_or(true, |r| c.regions.contains(&r)))
+			.min_by(|(_, a), (_, b)| {
+				a.center.distance_squared(start)
+					.partial_cmp(&b.center.distance_squared(start))
+					.unwrap_or(Ordering::Equal)
+			})
+			.map(|(i, _)| i);
+
+		if let Some(from_start) = analysis.distance_field(start) {
+			analysis.natural = bases
+				.iter()
+				.filter(|b| b.distance(start) > 5.0)
+				.filter_map(|b| analysis.distance_at(&from_start, *b).map(|d| (*b, d)))
+				.min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+				.map(|(b, _)| b);
+		}
+
+		if let Some(from_enemy) = analysis.distance_field(enemy_start) {
+			analysis.defensive_positions = bases
+				.iter()
+				.map(|b| (*b, analysis.step_towards(&from_enemy, *b, Self::DEFENSIVE_DISTANCE)))
+				.collect();
+		}
+
+		// The main is better held at the top of its ramp than partway down the path.
+		if let (Some(ramp), Some(main_region)) = (analysis.main_ramp, main_region) {
+			let ramp_center = analysis.chokes[ramp].center;
+			let top = analysis.regions[main_region]
+				.tiles
+				.iter()
+				.map(|t| tile_center(*t))
+				.min_by(|a, b| {
+					a.distance_squared(ramp_center)
+						.partial_cmp(&b.distance_squared(ramp_center))
+						.unwrap_or(Ordering::Equal)
+				});
+			if let (Some(top), Some(entry)) = (top, analysis.defensive_positions.iter_mut().find(|(b, _)| b.distance(start) < 1.0)) {
+				entry.1 = top;
+			}
+		}
+
+		analysis
+	}
+
+	/// Connected groups of set tiles in a grid.
+	fn components(grid: &Grid) -> Vec<Vec<Tile>> {
+		let mut seen = vec![false; grid.width() * grid.height()];
+		let mut components = Vec::new();
+
+		for y in 0..grid.height() {
+			for x in 0..grid.width() {
+				if !grid.get((x, y)) || seen[grid.index((x, y))] {
+					continue;
+				}
+
+				let mut tiles = Vec::new();
+				let mut queue = VecDeque::from([(x, y)]);
+				seen[grid.index((x, y))] = true;
+				while let Some(tile) = queue.pop_front() {
+					tiles.push(tile);
+					for n in grid.neighbours8(tile) {
+						if grid.get(n) && !seen[grid.index(n)] {
+							seen[grid.index(n)] = true;
+							queue.push_back(n);
+						}
+					}
+				}
+				components.push(tiles);
+			}
+		}
+
+		components
+	}
+
+	/// The region a point is in, or the closest one to it if it's on unbuildable ground.
+	pub fn region_near(&self, point: Point2) -> Option<usize> {
+		let tile = self.pathing.tile_at(point)?;
+		self.region_map[self.pathing.index(tile)].or_else(|| {
+			self.regions
+				.iter()
+				.enumerate()
+				.min_by(|(_, a), (_, b)| {
+					a.center.distance_squared(point)
+						.partial_cmp(&b.center.distance_squared(point))
+						.unwrap_or(Ordering::Equal)
+				})
+				.map(|(i, _)| i)
+		})
+	}
+
+	/// Ground distances from a point to everywhere else, moving in eight directions. Fields are
+	/// cached by the tile they start from, since the same few points get asked about every step.
+	pub fn distance_field(&self, from: Point2) -> Option<Rc<DistanceField>> {
+		let start = self.pathing.nearest_set(from, Self::SEARCH_RADIUS)?;
+		if let Some(field) = self.fields.borrow().get(&start) {
+			return Some(field.clone());
+		}
+
+		let field = Rc::new(self.measure_distances(start));
+		let mut fields = self.fields.borrow_mut();
+		if fields.len() >= Self::MAX_CACHED_FIELDS {
+			fields.clear();
+		}
+		fields.insert(start, field.clone());
+		Some(field)
+	}
+
+	/// Runs Dijkstra over the pathing grid from a walkable tile.
+	fn measure_distances(&self, start: Tile) -> DistanceField {
+		let mut distances = vec![f32::INFINITY; self.pathing.width() * self.pathing.height()];
+		let mut heap = BinaryHeap::new();
+
+		distances[self.pathing.index(start)] = 0.0;
+		heap.push(Frontier(0.0, start));
+
+		while let Some(Frontier(distance, tile)) = heap.pop() {
+			if distance > distances[self.pathing.index(tile)] {
+				continue;
+			}
+			for n in self.pathing.neighbours8(tile) {
+				if !self.pathing.get(n) {
+					continue;
+				}
+				let step = if n.0 != tile.0 && n.1 != tile.1 { std::f32::consts::SQRT_2 } else { 1.0 };
+				let next = distance + step;
+				if next < distances[self.pathing.index(n)] {
+					distances[self.pathing.index(n)] = next;
+					heap.push(Frontier(next, n));
+				}
+			}
+		}
+
+		DistanceField {
+			width: self.pathing.width(),
+			distances,
+		}
+	}
+
+	/// Ground distance recorded in a field to the walkable tile nearest a point.
+	pub fn distance_at(&self, field: &DistanceField, point: Point2) -> Option<f32> {
+		field.get(self.pathing.nearest_set(point, Self::SEARCH_RADIUS)?)
+	}
+
+	/// Ground distance between two points, or None if there is no path.
+	pub fn ground_distance(&self, from: Point2, to: Point2) -> Option<f32> {
+		self.distance_at(&self.distance_field(from)?, to)
+	}
+
+	/// Follows the shortest path from a point towards wherever a distance field was measured from,
+	/// for roughly the given distance.
+	pub fn step_towards(&self, field: &DistanceField, from: Point2, distance: f32) -> Point2 {
+		let mut tile = match self.pathing.nearest_set(from, Self::SEARCH_RADIUS) {
+			Some(tile) => tile,
+			None => return from,
+		};
+		let mut travelled = 0.0;
+
+		while travelled < distance {
+			let current = field.get(tile).unwrap_or(f32::INFINITY);
+			let next = self
+				.pathing
+				.neighbours8(tile)
+				.filter_map(|n| field.get(n).map(|d| (n, d)))
+				.filter(|(_, d)| *d < current)
+				.min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+			match next {
+				Some((n, d)) => {
+					travelled += current - d;
+					tile = n;
+				}
+				None => break,
+			}
+		}
+
+		tile_center(tile)
+	}
+
+	pub fn pathing(&self) -> &Grid {
+		&self.pathing
+	}
+
+	pub fn placement(&self) -> &Grid {
+		&self.placement
+	}
+
+	/// Whether a point can be walked on.
+	pub fn is_pathable(&self, point: Point2) -> bool {
+		self.pathing.tile_at(point).map_or(false, |t| self.pathing.get(t))
+	}
+
+	/// Tiles of the ramp out of our main, if we found one.
+	pub fn main_ramp_tiles(&self) -> &[Tile] {
+		match self.main_ramp {
+			Some(ramp) => self.chokes[ramp].tiles.as_slice(),
+			None => &[],
+		}
+	}
+
+	/// Where defenders of the given base should stand.
+	pub fn defensive_position(&self, base: Point2) -> Option<Point2> {
+		self.defensive_positions
+			.iter()
+			.find(|(b, _)| b.distance(base) < 1.0)
+			.map(|(_, position)| *position)
+	}
+}
+
+impl FlourishBot {
+	/// Builds the terrain analysis from the map's pathing and placement grids.
+	pub fn analyse_terrain(&mut self) {
+		let size = &self.game_info.map_size;
+		let (width, height) = (size.x, size.y);
+
+		let pathing = Grid::from_fn(width, height, |x, y| self.is_pathable((x, y)));
+		let placement = Grid::from_fn(width, height, |x, y| self.is_placeable((x, y)));
+		let bases = self.expansions.iter().map(|e| e.loc).collect::<Vec<Point2>>();
+
+		self.terrain = Some(TerrainAnalysis::new(
+			pathing,
+			placement,
+			self.start_location,
+			self.enemy_start,
+			&bases,
+		));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Two open 10x10 areas joined by a two tile wide unbuildable ramp in the middle rows.
+	fn two_regions() -> TerrainAnalysis {
+		let (width, height) = (24, 10);
+		let ramp = |x: usize, y: usize| (10..14).contains(&x) && (4..6).contains(&y);
+		let pathing = Grid::from_fn(width, height, |x, y| !(10..14).contains(&x) || ramp(x, y));
+		let placement = Grid::from_fn(width, height, |x, _| !(10..14).contains(&x));
+		TerrainAnalysis::new(pathing, placement, Point2::new(3.5, 5.5), Point2::new(20.5, 5.5), &[])
+	}
+
+	#[test]
+	fn finds_regions_and_the_choke_between_them() {
+		let terrain = two_regions();
+
+		assert_eq!(terrain.regions.len(), 2);
+		assert!(terrain.regions.iter().all(|r| r.tiles.len() == 100));
+		assert_eq!(terrain.chokes.len(), 1);
+		assert_eq!(terrain.chokes[0].tiles.len(), 8);
+		assert_eq!(terrain.chokes[0].regions, vec![0, 1]);
+		assert_eq!(terrain.main_ramp, Some(0));
+		assert_eq!(terrain.main_ramp_tiles().len(), 8);
+	}
+
+	#[test]
+	fn region_near_falls_back_to_the_closest_region() {
+		let terrain = two_regions();
+
+		let left = terrain.region_near(Point2::new(2.5, 2.5));
+		let right = terrain.region_near(Point2::new(20.5, 2.5));
+		assert!(left.is_some() && right.is_some() && left != right);
+		// On the ramp, closer to the left region's center than the right one's.
+		assert_eq!(terrain.region_near(Point2::new(10.5, 4.5)), left);
+	}
+
+	#[test]
+	fn ground_distance_in_the_open() {
+		let terrain = TerrainAnalysis::new(
+			Grid::from_fn(10, 10, |_, _| true),
+			Grid::from_fn(10, 10, |_, _| true),
+			Point2::new(0.5, 0.5),
+			Point2::new(9.5, 9.5),
+			&[],
+		);
+
+		let straight = terrain.ground_distance(Point2::new(0.5, 0.5), Point2::new(5.5, 0.5)).unwrap();
+		assert!((straight - 5.0).abs() < 1e-4);
+		let diagonal = terrain.ground_distance(Point2::new(0.5, 0.5), Point2::new(3.5, 3.5)).unwrap();
+		assert!((diagonal - 3.0 * std::f32::consts::SQRT_2).abs() < 1e-4);
+	}
+
+	#[test]
+	fn ground_distance_goes_around_walls() {
+		// A wall down x = 5 with a gap in the top row.
+		let gap = Grid::from_fn(10, 10, |x, y| x != 5 || y == 9);
+		let terrain = TerrainAnalysis::new(gap.clone(), gap, Point2::new(0.5, 0.5), Point2::new(9.5, 0.5), &[]);
+		let around = terrain.ground_distance(Point2::new(2.5, 0.5), Point2::new(7.5, 0.5)).unwrap();
+		assert!(around > 5.0 + 8.0);
+
+		let wall = Grid::from_fn(10, 10, |x, _| x != 5);
+		let terrain = TerrainAnalysis::new(wall.clone(), wall, Point2::new(0.5, 0.5), Point2::new(9.5, 0.5), &[]);
+		assert_eq!(terrain.ground_distance(Point2::new(2.5, 0.5), Point2::new(7.5, 0.5)), None);
+	}
+
+	#[test]
+	fn distance_fields_are_cached_by_start_tile() {
+		let terrain = two_regions();
+
+		let first = terrain.distance_field(Point2::new(2.5, 2.5)).unwrap();
+		let again = terrain.distance_field(Point2::new(2.9, 2.1)).unwrap();
+		let other = terrain.distance_field(Point2::new(20.5, 2.5)).unwrap();
+		assert!(Rc::ptr_eq(&first, &again));
+		assert!(!Rc::ptr_eq(&first, &other));
+	}
+}
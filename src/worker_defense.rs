@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_sc2::prelude::*;
+
+use crate::{flourish_bot::FlourishBot, targeting::TargetSelector};
+
+#[derive(Default)]
+pub struct WorkerDefense {
+	defenders: HashSet<u64>,
+	/// Enemy workers we've seen attack our units or buildings, and when they last did, in seconds.
+	attackers: HashMap<u64, f32>,
+}
+
+impl WorkerDefense {
+	/// How long an enemy worker counts as attacking after its last hit, so drones keep fighting it
+	/// between its attacks.
+	const ATTACKER_MEMORY: f32 = 5.0;
+
+	/// Whether the given drone has been pulled off mining to fight.
+	pub fn is_defending(&self, tag: u64) -> bool {
+		self.defenders.contains(&tag)
+	}
+
+	fn record_attack(&mut self, tag: u64, time: f32) {
+		self.attackers.insert(tag, time);
+	}
+
+	/// Whether the enemy worker with this tag has attacked us recently.
+	fn is_attacker(&self, tag: u64, time: f32) -> bool {
+		self.attackers.get(&tag).map_or(false, |last| *last + Self::ATTACKER_MEMORY >= time)
+	}
+
+	fn forget_old_attackers(&mut self, time: f32) {
+		self.attackers.retain(|_, last| *last + Self::ATTACKER_MEMORY >= time);
+	}
+}
+
+// Doing this in FlourishBot so we can have mutable access to WorkerDefense while viewing
+// units.
+impl FlourishBot {
+	/// How close to one of our townhalls an enemy has to be to count as in our mineral line.
+	const WORKER_DEFENSE_RADIUS: f32 = 12.0;
+	/// After this many seconds our army should handle anything that isn't a worker or cannon rush.
+	const WORKER_DEFENSE_EARLY_TIME: f32 = 4.0 * 60.0;
+	/// Defenders with less health than this go back to mining rather than die.
+	const DEFENDER_RETREAT_HEALTH: u32 = 15;
+	/// Extra distance past an enemy worker's range a hurt unit can be and still count as its target.
+	const WORKER_ATTACK_SLACK: f32 = 0.5;
+
+	/// How many drones it takes to deal with a single enemy unit or structure.
+	fn drones_to_fight(&self, enemy: &Unit) -> usize {
+		if enemy.is_worker() {
+			1
+		} else if enemy.is_structure() {
+			// Cannons, pylons and bunkers going up in our base; swarm them before they finish.
+			4
+		} else {
+			(enemy.supply_cost() * 2.0).ceil() as usize
+		}
+	}
+
+	/// Whether an enemy worker is hitting one of our units or buildings right now.
+	fn is_worker_attacking(&self, worker: &Unit) -> bool {
+		self.units
+			.my
+			.all
+			.iter()
+			.any(|u| self.surveillance.was_damaged(u.tag()) && worker.in_range(u, Self::WORKER_ATTACK_SLACK))
+	}
+
+	/// Remembers the enemy workers in our mineral lines that are hitting something of ours.
+	fn track_attacking_workers(&mut self) {
+		let time = self.time;
+		let attacking = self
+			.units
+			.enemy
+			.workers
+			.iter()
+			.filter(|w| self.is_worker_attacking(w))
+			.map(|w| w.tag())
+			.collect::<Vec<u64>>();
+		for tag in attacking {
+			self.worker_defense.record_attack(tag, time);
+		}
+		self.worker_defense.forget_old_attackers(time);
+	}
+
+	/// Enemies in one of our mineral lines that our drones should fight. A lone scouting worker
+	/// only counts once it has started attacking something.
+	fn worker_defense_threats(&self) -> Units {
+		let early = self.time < Self::WORKER_DEFENSE_EARLY_TIME;
+		let bases = self.units.my.townhalls.clone();
+		let near_bases = |e: &Unit| bases.iter().any(|b| b.is_closer(Self::WORKER_DEFENSE_RADIUS, e));
+		let worker_rush = self.units.enemy.workers.iter().filter(|e| near_bases(*e)).count() > 1;
+
+		self.units.enemy.all.filter(|e| {
+			!e.is_flying()
+				&& ((e.is_worker() && (worker_rush || self.worker_defense.is_attacker(e.tag(), self.time)))
+					|| (early && e.is_structure() && matches!(
+						e.type_id(),
+						UnitTypeId::Pylon | UnitTypeId::PhotonCannon | UnitTypeId::Bunker | UnitTypeId::SpineCrawler
+					))
+					|| (early && !e.is_structure() && e.can_attack_ground()))
+				&& near_bases(*e)
+		})
+	}
+
+	/// Pulls drones to fight worker rushes and early cheese in our mineral lines, and sends them
+	/// back to mining once the threat is gone.
+	pub fn defend_with_workers(&mut self) {
+		self.track_attacking_workers();
+		let threats = self.worker_defense_threats();
+
+		let mineral_fields = self.units.mineral_fields.clone();
+		let bases = self.units.my.townhalls.clone();
+		let send_to_mine = |u: &Unit| {
+			if let Some(base) = bases.closest(u) {
+				if let Some(mineral) = mineral_fields.closer(11.0, base).closest(u) {
+					u.gather(mineral.tag(), false);
+				}
+			}
+		};
+
+		// Defenders that died are dropped, and ones that are about to die go back to mining.
+		let workers = self.units.my.workers.clone();
+		self.worker_defense.defenders.retain(|tag| match workers.get(*tag) {
+			Some(u) if threats.is_empty() || u.hits().unwrap_or(0) < Self::DEFENDER_RETREAT_HEALTH => {
+				send_to_mine(u);
+				false
+			}
+			Some(_) => true,
+			None => false,
+		});
+
+		if threats.is_empty() {
+			return;
+		}
+
+		// Our own army near the threat means fewer drones need to come off the minerals.
+		let army_help = self
+			.units
+			.my
+			.units
+			.filter(|u| !u.is_worker() && u.can_attack_ground() && threats.iter().any(|t| t.is_closer(15.0, u)))
+			.iter()
+			.map(|u| (u.supply_cost() * 2.0) as usize)
+			.sum::<usize>();
+		let wanted = threats
+			.iter()
+			.map(|t| self.drones_to_fight(t))
+			.sum::<usize>()
+			.saturating_sub(army_help)
+			.min(workers.len().saturating_sub(1));
+
+		if self.worker_defense.defenders.len() < wanted {
+			let threat_center = threats.center().unwrap();
+			let mut candidates = workers.filter(|u| {
+				!self.worker_defense.defenders.contains(&u.tag())
+					&& !u.is_constructing()
+					&& u.hits().unwrap_or(0) > 2 * Self::DEFENDER_RETREAT_HEALTH
+			});
+			candidates.sort(|u| u.distance_squared(threat_center) as u32);
+
+			let to_add = wanted - self.worker_defense.defenders.len();
+			self.worker_defense.defenders.extend(candidates.iter().take(to_add).map(|u| u.tag()));
+		} else if self.worker_defense.defenders.len() > wanted {
+			let surplus = self.worker_defense.defenders.len() - wanted;
+			let released = self.worker_defense.defenders.iter().take(surplus).copied().collect::<Vec<u64>>();
+			for tag in released {
+				self.worker_defense.defenders.remove(&tag);
+				if let Some(u) = workers.get(tag) {
+					send_to_mine(u);
+				}
+			}
+		}
+
+		let mut selector = TargetSelector::new(&workers);
+		for tag in &self.worker_defense.defenders {
+			if let Some(u) = workers.get(*tag) {
+				if let Some(target) = self.choose_target(&mut selector, u, &threats) {
+					u.attack(Target::Tag(target.tag()), false);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Seconds per game step at the default step size.
+	const STEP: f32 = 1.0 / 22.4;
+
+	#[test]
+	fn scout_attacking_every_few_steps_stays_a_threat() {
+		let mut defense = WorkerDefense::default();
+		let scout = 1;
+
+		// The scout lands a hit every 20 steps, close to an SCV or probe's cooldown.
+		for step in 0..200 {
+			let time = step as f32 * STEP;
+			if step % 20 == 0 {
+				defense.record_attack(scout, time);
+			}
+			defense.forget_old_attackers(time);
+			assert!(defense.is_attacker(scout, time), "dropped on step {}", step);
+		}
+	}
+
+	#[test]
+	fn scout_that_stops_attacking_is_forgotten() {
+		let mut defense = WorkerDefense::default();
+		defense.record_attack(1, 10.0);
+
+		assert!(defense.is_attacker(1, 10.0 + WorkerDefense::ATTACKER_MEMORY));
+		defense.forget_old_attackers(10.0 + WorkerDefense::ATTACKER_MEMORY + 1.0);
+		assert!(!defense.is_attacker(1, 10.0 + WorkerDefense::ATTACKER_MEMORY + 1.0));
+		assert!(!defense.is_attacker(2, 10.0));
+	}
+}
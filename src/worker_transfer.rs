@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::flourish_bot::FlourishBot;
+
+#[derive(Default)]
+pub struct WorkerTransfer {
+	/// Townhalls we've rallied to a new base, with the new base's tag and where they rallied to
+	/// before.
+	redirected: HashMap<u64, (u64, Target)>,
+}
+
+impl FlourishBot {
+	/// How close an enemy unit can be to the path between two bases before we stop sending
+	/// drones along it.
+	const TRANSFER_DANGER_RADIUS: f32 = 10.0;
+	/// Distance between the points checked along a transfer path.
+	const TRANSFER_PATH_STEP: f32 = 4.0;
+
+	/// Moves drones from oversaturated bases to a townhall that has just finished, and rallies
+	/// the drones those bases make from now on to the new base.
+	pub fn transfer_workers_to_new_base(&mut self, new_base_tag: u64) {
+		let new_base = match self.units.my.townhalls.get(new_base_tag) {
+			Some(base) => base.clone(),
+			None => return,
+		};
+
+		let mut new_minerals = self.units.mineral_fields.closer(11.0, &new_base);
+		if new_minerals.is_empty() {
+			return;
+		}
+		new_minerals.sort(|m| std::cmp::Reverse(m.mineral_contents().unwrap_or(0)));
+		let new_mineral_tags = new_minerals.iter().map(|m| m.tag()).collect::<Vec<u64>>();
+		let rally_mineral = new_mineral_tags[0];
+
+		new_base.command(AbilityId::RallyWorkers, Target::Tag(rally_mineral), false);
+
+		// The new base won't have had its harvesters counted yet, so two per patch is the ideal.
+		let mut wanted = new_base
+			.ideal_harvesters()
+			.filter(|ideal| *ideal > 0)
+			.unwrap_or(2 * new_minerals.len() as u32) as usize;
+
+		let mut donors = self
+			.units
+			.my
+			.townhalls
+			.ready()
+			.filter(|b| b.tag() != new_base_tag && b.assigned_harvesters() > b.ideal_harvesters());
+		donors.sort(|b| b.distance_squared(&new_base) as u32);
+
+		let mut next_mineral = 0;
+		for donor in &donors {
+			if wanted == 0 {
+				break;
+			}
+			if !self.is_transfer_path_safe(donor.position(), new_base.position()) {
+				continue;
+			}
+
+			let excess = (donor.assigned_harvesters().unwrap() - donor.ideal_harvesters().unwrap()) as usize;
+			let local_minerals = self.local_mineral_tags(donor);
+
+			let movers = self
+				.units
+				.my
+				.workers
+				.iter()
+				.filter(|u| {
+					!u.is_carrying_resource()
+						&& !self.is_worker_reserved(u.tag())
+						&& u.target_tag().map_or(false, |tag| local_minerals.contains(&tag))
+				})
+				.take(excess.min(wanted))
+				.cloned()
+				.collect::<Vec<Unit>>();
+
+			for u in &movers {
+				u.gather(new_mineral_tags[next_mineral % new_mineral_tags.len()], false);
+				next_mineral += 1;
+			}
+			wanted -= movers.len();
+
+			let original = match donor.rally_targets().first() {
+				Some(rally) => rally.tag.map_or(Target::Pos(rally.point), Target::Tag),
+				None => Target::Pos(donor.position().towards(self.start_center, 5.0)),
+			};
+			self.worker_transfer.redirected.entry(donor.tag()).or_insert((new_base_tag, original));
+			donor.command(AbilityId::RallyWorkers, Target::Tag(rally_mineral), false);
+		}
+	}
+
+	/// Puts back the rally of every townhall we pointed at a new base, once that base has all the
+	/// drones it needs or is gone.
+	pub fn restore_worker_rallies(&mut self) {
+		let townhalls = self.units.my.townhalls.clone();
+		let done = self
+			.worker_transfer
+			.redirected
+			.iter()
+			.filter(|(_, (new_base, _))| {
+				townhalls.get(*new_base).map_or(true, |b| b.assigned_harvesters() >= b.ideal_harvesters())
+			})
+			.map(|(donor, _)| *donor)
+			.collect::<Vec<u64>>();
+
+		for donor in done {
+			if let Some((_, original)) = self.worker_transfer.redirected.remove(&donor) {
+				if let Some(townhall) = townhalls.get(donor) {
+					townhall.command(AbilityId::RallyWorkers, original, false);
+				}
+			}
+		}
+	}
+
+	/// Whether drones walking from one point to another would pass close to any enemy units that
+	/// can attack them. Follows the ground path when we have terrain analysis, and a straight line
+	/// otherwise.
+	fn is_transfer_path_safe(&self, from: Point2, to: Point2) -> bool {
+		let threats = self
+			.units
+			.enemy
+			.units
+			.filter(|e| e.can_attack_ground() && !e.is_worker());
+		if threats.is_empty() {
+			return true;
+		}
+
+		let mut path = vec![from];
+		match self.terrain.as_ref().and_then(|t| t.distance_field(to).map(|field| (t, field))) {
+			Some((terrain, field)) => {
+				let length = terrain.distance_at(&field, from).unwrap_or(0.0);
+				let mut travelled = 0.0;
+				let mut point = from;
+				while travelled < length {
+					point = terrain.step_towards(&field, point, Self::TRANSFER_PATH_STEP);
+					path.push(point);
+					travelled += Self::TRANSFER_PATH_STEP;
+				}
+			}
+			None => {
+				let length = from.distance(to);
+				let mut travelled = Self::TRANSFER_PATH_STEP;
+				while travelled <= length {
+					path.push(from.towards(to, travelled));
+					travelled += Self::TRANSFER_PATH_STEP;
+				}
+			}
+		}
+		path.push(to);
+
+		!path
+			.iter()
+			.any(|point| threats.iter().any(|e| e.is_closer(Self::TRANSFER_DANGER_RADIUS, *point)))
+	}
+}